@@ -0,0 +1,136 @@
+use crate::game_lib::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    Default,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Palette::Default,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn next(&self) -> Palette {
+        let index = Self::ALL.iter().position(|p| p == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccessibilityConfig {
+    pub palette: Palette,
+    /// Piece colors per palette, indexed by play box type with garbage last. Types
+    /// without an entry keep the color of the current theme.
+    pub palettes: HashMap<Palette, Vec<[u8; 4]>>,
+    pub glyphs: bool,
+    /// Glyph drawn on every block of a piece, indexed by play box type with garbage
+    /// last.
+    pub piece_glyphs: Vec<String>,
+    pub glyph_color: [u8; 4],
+    pub glyph_size: f32,
+}
+
+/// Accessibility options that can be changed while playing.
+#[derive(Resource, Debug, Clone)]
+pub struct AccessibilitySettings {
+    pub palette: Palette,
+    pub glyphs: bool,
+}
+
+impl AccessibilitySettings {
+    pub fn new(config: &AccessibilityConfig) -> Self {
+        Self {
+            palette: config.palette,
+            glyphs: config.glyphs,
+        }
+    }
+
+    pub fn piece_color(&self, type_index: usize, config: &AccessibilityConfig) -> Option<Color> {
+        if self.palette == Palette::Default {
+            return None;
+        }
+
+        config
+            .palettes
+            .get(&self.palette)
+            .and_then(|colors| colors.get(type_index))
+            .map(vec_to_color)
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct PieceGlyph;
+
+/// Returns the glyph overlay for a block of the given type, to be spawned as a child of
+/// the block entity. Its visibility is kept in sync by [`update_piece_glyphs`].
+pub fn piece_glyph(type_index: usize, game_lib: &GameLib) -> Option<impl Bundle> {
+    let config = &game_lib.config.accessibility_config;
+    let glyph = config.piece_glyphs.get(type_index)?;
+    if glyph.is_empty() {
+        return None;
+    }
+
+    Some((
+        Text2d::new(glyph.clone()),
+        TextFont {
+            font_size: config.glyph_size * game_lib.scale,
+            ..default()
+        },
+        TextColor(vec_to_color(&config.glyph_color)),
+        Transform::from_xyz(0.0, 0.0, 0.1),
+        Visibility::Hidden,
+        PieceGlyph,
+    ))
+}
+
+pub fn switch_accessibility(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<AccessibilitySettings>,
+) {
+    if keys.just_pressed(KeyCode::F4) {
+        settings.palette = settings.palette.next();
+        info!("Selected palette {:?}", settings.palette);
+    }
+
+    if keys.just_pressed(KeyCode::F5) {
+        settings.glyphs = !settings.glyphs;
+        info!(
+            "Piece glyphs {}",
+            if settings.glyphs { "on" } else { "off" }
+        );
+    }
+}
+
+pub fn update_piece_glyphs(
+    settings: Res<AccessibilitySettings>,
+    game_lib: Res<GameLib>,
+    mut glyphs: Query<(Ref<PieceGlyph>, &mut Visibility, &mut TextFont)>,
+) {
+    let visibility = if settings.glyphs {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let font_size = game_lib.config.accessibility_config.glyph_size * game_lib.scale;
+    let refresh = settings.is_changed() || game_lib.is_changed();
+
+    for (glyph, mut vis, mut font) in glyphs.iter_mut() {
+        if refresh || glyph.is_added() {
+            *vis = visibility;
+            font.font_size = font_size;
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::messages::*;
+use bevy::{audio::Volume, prelude::*};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundCue {
+    Move,
+    Rotate,
+    Lock,
+    #[serde(rename = "line_clear_1")]
+    LineClear1,
+    #[serde(rename = "line_clear_2")]
+    LineClear2,
+    #[serde(rename = "line_clear_3")]
+    LineClear3,
+    #[serde(rename = "line_clear_4")]
+    LineClear4,
+    Hold,
+    LevelUp,
+    GameOver,
+}
+
+impl SoundCue {
+    pub fn from_message(message: &GameplayMessage) -> Self {
+        match message {
+            GameplayMessage::Move => SoundCue::Move,
+            GameplayMessage::Rotate => SoundCue::Rotate,
+            GameplayMessage::Lock => SoundCue::Lock,
+            GameplayMessage::LineClear(0..=1) => SoundCue::LineClear1,
+            GameplayMessage::LineClear(2) => SoundCue::LineClear2,
+            GameplayMessage::LineClear(3) => SoundCue::LineClear3,
+            GameplayMessage::LineClear(_) => SoundCue::LineClear4,
+            GameplayMessage::Hold => SoundCue::Hold,
+            GameplayMessage::LevelUp(_) => SoundCue::LevelUp,
+            GameplayMessage::GameOver => SoundCue::GameOver,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Deserialize, Clone)]
+pub struct AudioConfig {
+    pub master_volume: f32,
+    pub cues: HashMap<SoundCue, CueConfig>,
+}
+
+/// A cue plays the sound file at `path` (relative to the asset folder) if given. The
+/// generated `tone` plays instead when there is no file or it fails to load.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CueConfig {
+    pub path: Option<String>,
+    pub tone: Option<ToneConfig>,
+    pub volume: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ToneConfig {
+    pub frequency: f32,
+    pub duration: f32,
+}
+
+impl AudioConfig {
+    pub fn volume(&self, cue: SoundCue) -> Option<f32> {
+        self.cues
+            .get(&cue)
+            .map(|c| (c.volume * self.master_volume).max(0.0))
+    }
+}
+
+/// A request to play a cue, written for every [`GameplayMessage`] that has a cue
+/// configured. This is the point to observe in tests that run without audio output.
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct PlaySound {
+    pub cue: SoundCue,
+    pub volume: f32,
+}
+
+struct SoundSource {
+    file: Option<Handle<AudioSource>>,
+    tone: Option<Handle<Pitch>>,
+}
+
+#[derive(Resource, Default)]
+pub struct SoundBank {
+    sources: HashMap<SoundCue, SoundSource>,
+}
+
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<GameplayMessage>()
+            .add_message::<PlaySound>()
+            .add_systems(
+                Update,
+                (
+                    request_sounds.run_if(resource_exists::<AudioConfig>),
+                    load_sound_bank
+                        .run_if(resource_added::<AudioConfig>)
+                        .run_if(resource_exists::<Assets<Pitch>>),
+                    play_sounds.run_if(resource_exists::<SoundBank>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+pub fn request_sounds(
+    mut gameplay_messages: MessageReader<GameplayMessage>,
+    mut play_sound: MessageWriter<PlaySound>,
+    audio_config: Res<AudioConfig>,
+) {
+    for message in gameplay_messages.read() {
+        let cue = SoundCue::from_message(message);
+        if let Some(volume) = audio_config.volume(cue) {
+            play_sound.write(PlaySound { cue, volume });
+        }
+    }
+}
+
+pub fn load_sound_bank(
+    mut commands: Commands,
+    audio_config: Res<AudioConfig>,
+    asset_server: Res<AssetServer>,
+    mut pitches: ResMut<Assets<Pitch>>,
+) {
+    let mut bank = SoundBank::default();
+
+    for (cue, config) in audio_config.cues.iter() {
+        let source = SoundSource {
+            file: config.path.as_ref().map(|path| asset_server.load(path)),
+            tone: config.tone.as_ref().map(|tone| {
+                pitches.add(Pitch::new(
+                    tone.frequency,
+                    Duration::from_secs_f32(tone.duration),
+                ))
+            }),
+        };
+        if source.file.is_none() && source.tone.is_none() {
+            warn!("Sound cue {:?} has neither a path nor a tone", cue);
+            continue;
+        }
+        bank.sources.insert(*cue, source);
+    }
+
+    info!("Sound bank loaded with {} cues", bank.sources.len());
+
+    commands.insert_resource(bank);
+}
+
+pub fn play_sounds(
+    mut commands: Commands,
+    mut play_sound: MessageReader<PlaySound>,
+    bank: Res<SoundBank>,
+    asset_server: Res<AssetServer>,
+) {
+    for request in play_sound.read() {
+        let Some(source) = bank.sources.get(&request.cue) else {
+            continue;
+        };
+        let settings = PlaybackSettings::DESPAWN.with_volume(Volume::Linear(request.volume));
+        let file = source
+            .file
+            .as_ref()
+            .filter(|handle| !asset_server.load_state(handle.id()).is_failed());
+        match (file, &source.tone) {
+            (Some(handle), _) => {
+                commands.spawn((AudioPlayer(handle.clone()), settings));
+            }
+            (None, Some(handle)) => {
+                commands.spawn((AudioPlayer(handle.clone()), settings));
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_lib::*;
+    use std::path::Path;
+
+    fn cue(volume: f32) -> CueConfig {
+        CueConfig {
+            path: None,
+            tone: None,
+            volume,
+        }
+    }
+
+    fn requested_cues(audio_config: AudioConfig, messages: &[GameplayMessage]) -> Vec<PlaySound> {
+        let mut app = App::new();
+        app.add_message::<GameplayMessage>()
+            .add_message::<PlaySound>()
+            .insert_resource(audio_config)
+            .add_systems(Update, request_sounds);
+
+        for message in messages {
+            app.world_mut().write_message(*message);
+        }
+        app.update();
+
+        app.world_mut()
+            .resource_mut::<Messages<PlaySound>>()
+            .drain()
+            .collect()
+    }
+
+    #[test]
+    fn line_clears_map_to_cues_by_count() {
+        let counts = [1, 2, 3, 4, 5].map(GameplayMessage::LineClear);
+        let cues: Vec<SoundCue> = counts.iter().map(SoundCue::from_message).collect();
+
+        assert_eq!(
+            cues,
+            [
+                SoundCue::LineClear1,
+                SoundCue::LineClear2,
+                SoundCue::LineClear3,
+                SoundCue::LineClear4,
+                SoundCue::LineClear4,
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_request_configured_cues_scaled_by_master_volume() {
+        let audio_config = AudioConfig {
+            master_volume: 0.5,
+            cues: HashMap::from([
+                (SoundCue::Move, cue(0.4)),
+                (SoundCue::LineClear2, cue(1.0)),
+                (SoundCue::GameOver, cue(0.8)),
+            ]),
+        };
+
+        let requested = requested_cues(
+            audio_config,
+            &[
+                GameplayMessage::Move,
+                GameplayMessage::Rotate,
+                GameplayMessage::LineClear(2),
+                GameplayMessage::LevelUp(3),
+                GameplayMessage::GameOver,
+            ],
+        );
+
+        assert_eq!(
+            requested,
+            [
+                PlaySound {
+                    cue: SoundCue::Move,
+                    volume: 0.2,
+                },
+                PlaySound {
+                    cue: SoundCue::LineClear2,
+                    volume: 0.5,
+                },
+                PlaySound {
+                    cue: SoundCue::GameOver,
+                    volume: 0.4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn shipped_cues_have_a_sound_file_and_a_fallback_tone() {
+        let game_lib = GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap();
+
+        for (cue, config) in game_lib.config.audio_config.cues.iter() {
+            let path = config.path.as_ref().unwrap();
+            assert!(
+                Path::new("assets").join(path).is_file(),
+                "{:?} plays missing {}",
+                cue,
+                path
+            );
+            assert!(config.tone.is_some(), "{:?} has no fallback tone", cue);
+        }
+    }
+}
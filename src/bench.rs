@@ -0,0 +1,202 @@
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::headless::*;
+use crate::my_error::MyError;
+use crate::replay::*;
+use crate::simulation::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
+};
+
+/// Spread of a value over all games of a benchmark.
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct Distribution {
+    pub min: f64,
+    pub p10: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Distribution {
+    pub fn new(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+
+        values.sort_by(f64::total_cmp);
+        let percentile = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+
+        Self {
+            min: values[0],
+            p10: percentile(0.1),
+            median: percentile(0.5),
+            p90: percentile(0.9),
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub config_hash: String,
+    pub games: usize,
+    pub threads: usize,
+    /// Wall clock seconds the games took.
+    pub elapsed: f64,
+    pub lines: Distribution,
+    pub score: Distribution,
+    pub pieces: Distribution,
+    /// Pieces placed per second of wall clock time, over all threads.
+    pub pieces_per_sec: f64,
+    /// Games ended by `max_pieces` instead of topping out.
+    pub capped_games: usize,
+}
+
+impl BenchReport {
+    fn print_table(&self) {
+        println!(
+            "{} games on {} threads in {:.2}s, config {}",
+            self.games, self.threads, self.elapsed, self.config_hash
+        );
+        println!(
+            "{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "", "min", "p10", "median", "p90", "max", "mean"
+        );
+        for (name, d) in [
+            ("lines", &self.lines),
+            ("score", &self.score),
+            ("pieces", &self.pieces),
+        ] {
+            println!(
+                "{:<8} {:>10.0} {:>10.0} {:>10.0} {:>10.0} {:>10.0} {:>10.1}",
+                name, d.min, d.p10, d.median, d.p90, d.max, d.mean
+            );
+        }
+        println!("{:.0} pieces/sec", self.pieces_per_sec);
+        if self.capped_games > 0 {
+            println!("{} games hit the piece limit", self.capped_games);
+        }
+    }
+}
+
+/// Number of worker threads to use when none is asked for.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Runs `f` on every job, spread over `threads` threads that each pick the next job
+/// once they are done, and returns the results in the order of the jobs.
+pub fn parallel_map<T, R, F>(jobs: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next_job = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results: Vec<(usize, R)> = Vec::new();
+                    loop {
+                        let i = next_job.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(i) else {
+                            break;
+                        };
+                        results.push((i, f(job)));
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Lets the bot play a game with `weights` on the given seed.
+pub fn play_game(
+    game_lib: &GameLib,
+    seed: u64,
+    weights: &BotWeights,
+    max_pieces: u64,
+) -> GameResult {
+    let mut simulation = Simulation::new(game_lib, seed);
+    play_simulation(&mut simulation, game_lib, weights, max_pieces);
+    simulation.result()
+}
+
+/// Bot weights to play with: the `--weights` file if given, otherwise the config ones.
+pub fn bot_weights(args: &Args, game_lib: &GameLib) -> Result<BotWeights, MyError> {
+    match &args.weights {
+        Some(path) => BotWeights::load(path),
+        None => Ok(game_lib.config.bot_config.weights),
+    }
+}
+
+pub fn run_bench(args: &Args, bench: &BenchArgs) -> Result<(), MyError> {
+    let game_lib = GameLib::new(
+        &args.config_path,
+        &mut Assets::default(),
+        &mut Assets::default(),
+        true,
+    )?;
+    let threads = bench.threads.unwrap_or_else(default_threads);
+    let seeds: Vec<u64> = (0..bench.games).map(|i| bench.first_seed + i).collect();
+    let weights = bot_weights(args, &game_lib)?;
+
+    info!(
+        "Benchmark started: games={} threads={}",
+        seeds.len(),
+        threads
+    );
+
+    let start = Instant::now();
+    let results = parallel_map(&seeds, threads, |seed| {
+        play_game(&game_lib, *seed, &weights, bench.max_pieces)
+    });
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let capped = |r: &&GameResult| r.pieces >= bench.max_pieces;
+    // The piece still falling in a capped game was never placed.
+    let placed: u64 = results.iter().map(|r| r.pieces).sum::<u64>()
+        - results.iter().filter(capped).count() as u64;
+    let distribution =
+        |value: fn(&GameResult) -> f64| Distribution::new(results.iter().map(value).collect());
+    let report = BenchReport {
+        config_hash: config_hash(&args.config_path)?,
+        games: results.len(),
+        threads,
+        elapsed,
+        lines: distribution(|r| r.lines as f64),
+        score: distribution(|r| r.score as f64),
+        pieces: distribution(|r| r.pieces as f64),
+        pieces_per_sec: placed as f64 / elapsed.max(f64::EPSILON),
+        capped_games: results.iter().filter(capped).count(),
+    };
+
+    info!("Benchmark finished: {:?}", report);
+
+    if bench.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        report.print_table();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoxPos {
+    pub row: i32,
+    pub col: i32,
+}
+
+impl BoxPos {
+    pub fn new(row: i32, col: i32) -> Self {
+        Self { row, col }
+    }
+}
+
+/// A change made to a [`Board`], used to keep its rendering in sync.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoardChange {
+    /// Blocks of the given play box type were added at `cells`.
+    Placed {
+        type_index: usize,
+        cells: Vec<BoxPos>,
+    },
+    /// Full `rows` were removed and the rows above them moved down, listed as
+    /// `(from, to)` pairs in ascending order.
+    RowsRemoved {
+        rows: Vec<usize>,
+        moves: Vec<(usize, usize)>,
+    },
+    /// All rows moved up by `count`, dropping those pushed past the top, and garbage
+    /// blocks of the given type were added at `cells` in the freed bottom rows.
+    RowsRaised {
+        count: usize,
+        type_index: usize,
+        cells: Vec<BoxPos>,
+    },
+    /// All blocks were removed.
+    Cleared,
+}
+
+/// Occupancy of the game panel by play box type, along with the game rules that act
+/// on it. Rows are counted from the bottom; rows at and above `main_rows` are hidden
+/// and only used to spawn new pieces.
+#[derive(Debug, Clone)]
+pub struct Board {
+    cells: Vec<Vec<Option<usize>>>,
+    main_rows: usize,
+    height: usize,
+    full_rows: Vec<usize>,
+    changes: Vec<BoardChange>,
+}
+
+impl Board {
+    pub fn new(row_count: usize, col_count: usize, main_rows: usize) -> Self {
+        Self {
+            cells: vec![vec![None; col_count]; row_count],
+            main_rows,
+            height: 0,
+            full_rows: Vec::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn row_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[inline]
+    pub fn col_count(&self) -> usize {
+        self.cells[0].len()
+    }
+
+    #[inline]
+    pub fn main_rows(&self) -> usize {
+        self.main_rows
+    }
+
+    /// Number of rows from the bottom up to the highest occupied one.
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Rows filled by the last placed piece, in ascending order.
+    #[inline]
+    pub fn full_rows(&self) -> &[usize] {
+        &self.full_rows
+    }
+
+    /// Play box type of every cell, indexed `[row][col]`.
+    #[inline]
+    pub fn cells(&self) -> &Vec<Vec<Option<usize>>> {
+        &self.cells
+    }
+
+    #[inline]
+    pub fn is_inside(&self, row: i32, col: i32) -> bool {
+        (0..self.row_count() as i32).contains(&row) && (0..self.col_count() as i32).contains(&col)
+    }
+
+    /// Returns whether a piece made of the `shape` offsets fits at `pos`.
+    pub fn can_place(&self, pos: &BoxPos, shape: &[BoxPos]) -> bool {
+        shape.iter().all(|p| {
+            let (row, col) = (pos.row + p.row, pos.col + p.col);
+            self.is_inside(row, col) && self.cells[row as usize][col as usize].is_none()
+        })
+    }
+
+    /// Returns the lowest position the piece can drop to straight down from `pos`.
+    pub fn drop_pos(&self, pos: &BoxPos, shape: &[BoxPos]) -> BoxPos {
+        let mut result = pos.clone();
+        while self.can_place(&BoxPos::new(result.row - 1, result.col), shape) {
+            result.row -= 1;
+        }
+        result
+    }
+
+    /// Locks a piece into the board and records the rows it filled. Returns false,
+    /// leaving the board untouched, if the piece does not fit.
+    pub fn place(&mut self, pos: &BoxPos, shape: &[BoxPos], type_index: usize) -> bool {
+        if !self.can_place(pos, shape) {
+            return false;
+        }
+
+        let cells: Vec<BoxPos> = shape
+            .iter()
+            .map(|p| BoxPos::new(pos.row + p.row, pos.col + p.col))
+            .collect();
+        for cell in cells.iter() {
+            self.cells[cell.row as usize][cell.col as usize] = Some(type_index);
+            self.height = self.height.max(cell.row as usize + 1);
+        }
+
+        let mut rows: Vec<usize> = cells.iter().map(|c| c.row as usize).collect();
+        rows.sort_unstable();
+        rows.dedup();
+        self.full_rows = rows.into_iter().filter(|r| self.is_full_row(*r)).collect();
+
+        self.changes.push(BoardChange::Placed { type_index, cells });
+        true
+    }
+
+    #[inline]
+    pub fn has_full_rows(&self) -> bool {
+        !self.full_rows.is_empty()
+    }
+
+    #[inline]
+    pub fn reach_top(&self) -> bool {
+        self.height >= self.main_rows
+    }
+
+    /// Removes the rows filled by the last placed piece and moves the rows above
+    /// them down. Returns the largest number of rows any of them moved.
+    pub fn remove_full_rows(&mut self) -> usize {
+        if self.full_rows.is_empty() {
+            return 0;
+        }
+
+        let rows = std::mem::take(&mut self.full_rows);
+        let mut moves: Vec<(usize, usize)> = Vec::new();
+        let mut removed = 0;
+
+        for row in 0..self.height {
+            if rows.contains(&row) {
+                removed += 1;
+            } else if removed > 0 {
+                self.cells[row - removed] = self.cells[row].clone();
+                moves.push((row, row - removed));
+            }
+        }
+
+        let col_count = self.col_count();
+        for row in self.height - removed..self.height {
+            self.cells[row] = vec![None; col_count];
+        }
+        self.height -= removed;
+
+        let fall = moves.iter().map(|(from, to)| from - to).max().unwrap_or(0);
+        self.changes.push(BoardChange::RowsRemoved { rows, moves });
+        fall
+    }
+
+    /// Pushes the stack up by `count` rows and fills the freed bottom rows with blocks
+    /// of `type_index`, leaving `hole_col` empty so they can be cleared.
+    pub fn add_garbage(&mut self, count: usize, hole_col: usize, type_index: usize) {
+        let count = count.min(self.row_count());
+        if count == 0 {
+            return;
+        }
+
+        let col_count = self.col_count();
+        self.cells.truncate(self.row_count() - count);
+        let mut cells: Vec<BoxPos> = Vec::new();
+        for row in 0..count {
+            let garbage: Vec<Option<usize>> = (0..col_count)
+                .map(|col| (col != hole_col).then_some(type_index))
+                .collect();
+            cells.extend(
+                (0..col_count)
+                    .filter(|col| *col != hole_col)
+                    .map(|col| BoxPos::new(row as i32, col as i32)),
+            );
+            self.cells.insert(0, garbage);
+        }
+        self.height = (self.height + count).min(self.row_count());
+
+        self.changes.push(BoardChange::RowsRaised {
+            count,
+            type_index,
+            cells,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        let col_count = self.col_count();
+        for row in self.cells.iter_mut() {
+            *row = vec![None; col_count];
+        }
+        self.height = 0;
+        self.full_rows.clear();
+        self.changes.push(BoardChange::Cleared);
+    }
+
+    /// Fills an empty board with the cells returned by [`Board::cells`].
+    pub fn restore(&mut self, cells: &[Vec<Option<usize>>]) {
+        for (row, types) in cells.iter().enumerate().take(self.row_count()) {
+            for (col, type_index) in types.iter().enumerate().take(self.col_count()) {
+                if let Some(type_index) = type_index {
+                    self.place(
+                        &BoxPos::new(row as i32, col as i32),
+                        &[BoxPos::default()],
+                        *type_index,
+                    );
+                }
+            }
+        }
+        self.full_rows.clear();
+    }
+
+    #[inline]
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// Draws the bottom `count` rows, top to bottom, with `#` for a block and `.` for
+    /// an empty cell.
+    pub fn ascii_rows(&self, count: usize) -> Vec<String> {
+        self.cells[..count.min(self.row_count())]
+            .iter()
+            .rev()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| if cell.is_some() { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns and forgets the changes made since the last call.
+    pub fn take_changes(&mut self) -> Vec<BoardChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    fn is_full_row(&self, row: usize) -> bool {
+        self.cells[row].iter().all(|cell| cell.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell() -> Vec<BoxPos> {
+        vec![BoxPos::default()]
+    }
+
+    fn column(height: i32) -> Vec<BoxPos> {
+        (0..height).map(|row| BoxPos::new(row, 0)).collect()
+    }
+
+    #[test]
+    fn place_records_full_rows_and_changes() {
+        let mut board = Board::new(6, 3, 4);
+        assert!(board.place(&BoxPos::new(0, 0), &cell(), 1));
+        assert!(board.place(&BoxPos::new(0, 1), &cell(), 1));
+        assert!(!board.has_full_rows());
+        assert_eq!(board.height(), 1);
+
+        assert!(board.place(&BoxPos::new(0, 2), &column(2), 2));
+        assert_eq!(board.full_rows(), &[0]);
+        assert_eq!(board.height(), 2);
+        assert_eq!(board.cells()[1][2], Some(2));
+
+        let changes = board.take_changes();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[2],
+            BoardChange::Placed {
+                type_index: 2,
+                cells: vec![BoxPos::new(0, 2), BoxPos::new(1, 2)],
+            }
+        );
+        assert!(!board.has_changes());
+    }
+
+    #[test]
+    fn place_rejects_overlapping_or_outside_pieces() {
+        let mut board = Board::new(6, 3, 4);
+        assert!(board.place(&BoxPos::new(0, 0), &cell(), 1));
+        board.take_changes();
+
+        assert!(!board.place(&BoxPos::new(0, 0), &cell(), 2));
+        assert!(!board.place(&BoxPos::new(5, 0), &column(2), 2));
+        assert!(!board.place(&BoxPos::new(0, 3), &cell(), 2));
+        assert_eq!(board.cells()[0][0], Some(1));
+        assert!(!board.has_changes());
+    }
+
+    #[test]
+    fn remove_full_rows_moves_rows_between_and_above_cleared_ones() {
+        let mut board = Board::new(6, 3, 4);
+        for col in 0..2 {
+            board.place(&BoxPos::new(0, col), &cell(), 1);
+            board.place(&BoxPos::new(2, col), &cell(), 1);
+        }
+        board.place(&BoxPos::new(1, 0), &cell(), 3);
+        board.place(&BoxPos::new(3, 1), &cell(), 4);
+        board.place(&BoxPos::new(0, 2), &column(3), 2);
+        assert_eq!(board.full_rows(), &[0, 2]);
+        board.take_changes();
+
+        assert_eq!(board.remove_full_rows(), 2);
+        assert_eq!(
+            board.take_changes(),
+            vec![BoardChange::RowsRemoved {
+                rows: vec![0, 2],
+                moves: vec![(1, 0), (3, 1)],
+            }]
+        );
+        assert_eq!(board.cells()[0], vec![Some(3), None, Some(2)]);
+        assert_eq!(board.cells()[1], vec![None, Some(4), None]);
+        assert!(board.cells()[2..].iter().flatten().all(|c| c.is_none()));
+        assert_eq!(board.height(), 2);
+        assert!(!board.has_full_rows());
+        assert_eq!(board.remove_full_rows(), 0);
+    }
+
+    #[test]
+    fn drop_pos_stops_on_blocks_and_floor() {
+        let mut board = Board::new(6, 3, 4);
+        assert_eq!(
+            board.drop_pos(&BoxPos::new(4, 0), &column(2)),
+            BoxPos::new(0, 0)
+        );
+
+        board.place(&BoxPos::new(0, 1), &column(3), 1);
+        assert_eq!(
+            board.drop_pos(&BoxPos::new(4, 1), &cell()),
+            BoxPos::new(3, 1)
+        );
+        assert_eq!(
+            board.drop_pos(&BoxPos::new(2, 1), &cell()),
+            BoxPos::new(2, 1)
+        );
+    }
+
+    #[test]
+    fn reach_top_once_the_top_visible_row_is_used() {
+        let mut board = Board::new(6, 3, 4);
+        board.place(&BoxPos::new(0, 0), &column(3), 1);
+        assert!(!board.reach_top());
+        board.place(&BoxPos::new(3, 0), &cell(), 1);
+        assert!(board.reach_top());
+    }
+
+    #[test]
+    fn restore_rebuilds_cells_and_height_without_full_rows() {
+        let mut board = Board::new(6, 3, 4);
+        board.place(&BoxPos::new(0, 0), &cell(), 1);
+        board.place(&BoxPos::new(0, 1), &cell(), 2);
+        board.place(&BoxPos::new(0, 2), &column(2), 3);
+        let cells = board.cells().clone();
+
+        let mut restored = Board::new(6, 3, 4);
+        restored.restore(&cells);
+        assert_eq!(restored.cells(), &cells);
+        assert_eq!(restored.height(), 2);
+        assert!(!restored.has_full_rows());
+    }
+}
@@ -0,0 +1,377 @@
+use crate::board::*;
+use crate::game_lib::*;
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::play_box::*;
+use crate::replay::*;
+use crate::rules::*;
+use crate::simulation::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Weights of the board features the bot scores placements with. Features that make a
+/// board worse should get negative weights.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct BotWeights {
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub lines: f32,
+}
+
+impl BotWeights {
+    /// Loads weights written by the `tune` command.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let weights = read_json(path.as_ref())?;
+        info!("Loaded bot weights from {}", path.as_ref().display());
+        Ok(weights)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BotConfig {
+    pub weights: BotWeights,
+    /// Seconds between two moves of the bot.
+    pub move_interval: f32,
+}
+
+/// Board properties the bot judges a placement by.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoardFeatures {
+    /// Sum of the column heights.
+    pub aggregate_height: u32,
+    /// Empty cells with a block somewhere above them.
+    pub holes: u32,
+    /// Sum of the height differences of neighbouring columns.
+    pub bumpiness: u32,
+    pub lines: u32,
+}
+
+impl BoardFeatures {
+    pub fn new(board: &Board, lines: u32) -> Self {
+        let cells = board.cells();
+        let mut heights: Vec<u32> = vec![0; board.col_count()];
+        let mut holes = 0;
+
+        for (col, height) in heights.iter_mut().enumerate() {
+            let mut top_found = false;
+            for row in (0..board.height()).rev() {
+                if cells[row][col].is_some() {
+                    if !top_found {
+                        *height = row as u32 + 1;
+                        top_found = true;
+                    }
+                } else if top_found {
+                    holes += 1;
+                }
+            }
+        }
+
+        Self {
+            aggregate_height: heights.iter().sum(),
+            holes,
+            bumpiness: heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum(),
+            lines,
+        }
+    }
+
+    pub fn score(&self, weights: &BotWeights) -> f32 {
+        weights.aggregate_height * self.aggregate_height as f32
+            + weights.holes * self.holes as f32
+            + weights.bumpiness * self.bumpiness as f32
+            + weights.lines * self.lines as f32
+    }
+}
+
+/// A piece dropped straight down to its final position.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub index: BoxIndex,
+    pub pos: BoxPos,
+}
+
+impl Placement {
+    /// Returns the board after locking the piece and clearing full rows, along with
+    /// the number of rows cleared.
+    pub fn apply(&self, board: &Board, game_lib: &GameLib) -> (Board, u32) {
+        let mut board = board.clone();
+        board.place(
+            &self.pos,
+            game_lib.box_pos(&self.index),
+            self.index.type_index,
+        );
+        let lines = board.full_rows().len() as u32;
+        board.remove_full_rows();
+        (board, lines)
+    }
+}
+
+/// Every placement a piece at `pos` can reach by rotating in place and then moving
+/// sideways before dropping.
+pub fn reachable_placements(
+    board: &Board,
+    index: &BoxIndex,
+    pos: &BoxPos,
+    game_lib: &GameLib,
+) -> Vec<Placement> {
+    let mut result: Vec<Placement> = Vec::new();
+    let mut rotated = index.clone();
+
+    for turns in 0..PLAY_BOX_ROTATE_COUNT {
+        if turns > 0 {
+            rotated.rotate(Rotation::Cw);
+            if !board.can_place(pos, game_lib.box_pos(&rotated)) {
+                break;
+            }
+        }
+
+        let shape = game_lib.box_pos(&rotated);
+        for (start, step) in [(pos.col, -1), (pos.col + 1, 1)] {
+            let mut col = start;
+            while board.can_place(&BoxPos::new(pos.row, col), shape) {
+                result.push(Placement {
+                    index: rotated.clone(),
+                    pos: board.drop_pos(&BoxPos::new(pos.row, col), shape),
+                });
+                col += step;
+            }
+        }
+    }
+
+    result
+}
+
+/// Picks the placement of the piece at `pos` with the best score. When the `next`
+/// piece is known, each placement is scored by the best board reachable after also
+/// placing the next piece.
+pub fn best_placement(
+    board: &Board,
+    index: &BoxIndex,
+    pos: &BoxPos,
+    next: Option<&BoxIndex>,
+    game_lib: &GameLib,
+    weights: &BotWeights,
+) -> Option<Placement> {
+    let mut best: Option<(f32, Placement)> = None;
+
+    for placement in reachable_placements(board, index, pos, game_lib) {
+        let (after, lines) = placement.apply(board, game_lib);
+        let next_pos = next
+            .and_then(|next| init_box_pos(&after, next, game_lib).map(|next_pos| (next, next_pos)));
+
+        let score = match (next, next_pos) {
+            (None, _) => BoardFeatures::new(&after, lines).score(weights),
+            // The next piece would not fit, which ends the game.
+            (Some(_), None) => f32::MIN,
+            (Some(_), Some((next, next_pos))) => {
+                reachable_placements(&after, next, &next_pos, game_lib)
+                    .iter()
+                    .map(|p| {
+                        let (last, more_lines) = p.apply(&after, game_lib);
+                        BoardFeatures::new(&last, lines + more_lines).score(weights)
+                    })
+                    .fold(f32::MIN, f32::max)
+            }
+        };
+
+        if best.as_ref().is_none_or(|(s, _)| score > *s) {
+            best = Some((score, placement));
+        }
+    }
+
+    best.map(|(_, placement)| placement)
+}
+
+/// Lets the bot play a simulated game until it tops out or `max_pieces` pieces have
+/// entered the board, putting every piece straight at its best placement. The last
+/// piece of a capped game is left falling.
+pub fn play_simulation(
+    simulation: &mut Simulation,
+    game_lib: &GameLib,
+    weights: &BotWeights,
+    max_pieces: u64,
+) {
+    while simulation.rules.pieces() < max_pieces
+        && let Some((index, pos)) = simulation.rules.piece()
+        && let Some(placement) =
+            best_placement(&simulation.rules.board, index, pos, None, game_lib, weights)
+    {
+        simulation.place(&placement);
+    }
+}
+
+/// Where the bot wants the current play box to end up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotPlan {
+    pub rotate_index: usize,
+    pub col: i32,
+    /// Pieces spawned when the plan was made, so a different count means the plan is
+    /// for an older piece.
+    pub pieces: u64,
+}
+
+/// Picks the best placement for the falling piece among every rotation and column it
+/// can reach from where it is now.
+pub fn plan_move(rules: &GameRules, game_lib: &GameLib, weights: &BotWeights) -> Option<BotPlan> {
+    let (index, pos) = rules.piece()?;
+    let placement = best_placement(&rules.board, index, pos, None, game_lib, weights)?;
+
+    Some(BotPlan {
+        rotate_index: placement.index.rotate_index,
+        col: placement.pos.col,
+        pieces: rules.pieces(),
+    })
+}
+
+/// Computer player, enabled with `--bot`. It plans a placement for every new play box
+/// and then steps towards it, one move per `move_interval`.
+#[derive(Resource, Debug)]
+pub struct Bot {
+    config: BotConfig,
+    plan: Option<BotPlan>,
+    cooldown: f32,
+}
+
+impl Bot {
+    pub fn new(config: &BotConfig) -> Self {
+        Self {
+            config: config.clone(),
+            plan: None,
+            cooldown: 0.0,
+        }
+    }
+
+    /// Returns the next action taking the falling piece to the planned placement.
+    fn next_action(&self, rules: &GameRules) -> Option<InputAction> {
+        let (plan, (index, pos)) = (self.plan.as_ref()?, rules.piece()?);
+        let col = pos.col;
+
+        Some(if index.rotate_index != plan.rotate_index {
+            InputAction::RotateCw
+        } else if col < plan.col {
+            InputAction::MoveRight
+        } else if col > plan.col {
+            InputAction::MoveLeft
+        } else {
+            InputAction::HardDrop
+        })
+    }
+}
+
+/// Queues the bot's actions ahead of the tick, so that replays record them like player
+/// input.
+pub fn run_bot(
+    time: Res<Time>,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    mut bot: ResMut<Bot>,
+    mut pending: ResMut<PendingActions>,
+) {
+    if game.piece().is_none() {
+        return;
+    }
+
+    if bot
+        .plan
+        .as_ref()
+        .is_none_or(|plan| plan.pieces != game.pieces())
+    {
+        bot.plan = plan_move(&game, game_lib.as_ref(), &bot.config.weights);
+        bot.cooldown = bot.config.move_interval;
+    }
+
+    bot.cooldown -= time.delta_secs();
+    if bot.cooldown > 0.0 {
+        return;
+    }
+    bot.cooldown += bot.config.move_interval;
+
+    if let Some(action) = bot.next_action(&game) {
+        pending.0.merge(&ActionFrame {
+            actions: vec![action],
+            target_col: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_with(cells: &[(i32, i32)]) -> Board {
+        let mut board = Board::new(6, 4, 4);
+        for (row, col) in cells {
+            board.place(&BoxPos::new(*row, *col), &[BoxPos::default()], 0);
+        }
+        board
+    }
+
+    #[test]
+    fn empty_board_has_no_features() {
+        assert_eq!(
+            BoardFeatures::new(&board_with(&[]), 0),
+            BoardFeatures::default()
+        );
+    }
+
+    #[test]
+    fn features_count_heights_holes_and_bumpiness() {
+        // Column heights 3, 3, 0, 1 with two holes under the block in column 1.
+        let board = board_with(&[(0, 0), (1, 0), (2, 0), (2, 1), (0, 3)]);
+        assert_eq!(
+            BoardFeatures::new(&board, 2),
+            BoardFeatures {
+                aggregate_height: 7,
+                holes: 2,
+                bumpiness: 4,
+                lines: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn holes_count_every_empty_cell_below_the_top() {
+        let board = board_with(&[(0, 1), (3, 1), (4, 1)]);
+        let features = BoardFeatures::new(&board, 0);
+        assert_eq!(features.holes, 2);
+        assert_eq!(features.aggregate_height, 5);
+        assert_eq!(features.bumpiness, 10);
+    }
+
+    #[test]
+    fn score_weights_each_feature() {
+        let features = BoardFeatures {
+            aggregate_height: 7,
+            holes: 2,
+            bumpiness: 4,
+            lines: 1,
+        };
+        let weights = BotWeights {
+            aggregate_height: -1.0,
+            holes: -10.0,
+            bumpiness: -0.5,
+            lines: 3.0,
+        };
+        assert_eq!(features.score(&weights), -7.0 - 20.0 - 2.0 + 3.0);
+    }
+
+    #[test]
+    fn simulated_games_stop_at_max_pieces() {
+        let game_lib = GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap();
+        let weights = game_lib.config.bot_config.weights;
+        let mut simulation = Simulation::new(&game_lib, 3);
+        play_simulation(&mut simulation, &game_lib, &weights, 20);
+
+        assert!(!simulation.is_over());
+        assert_eq!(simulation.result().pieces, 20);
+    }
+}
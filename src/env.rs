@@ -0,0 +1,312 @@
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::play_box::*;
+use crate::simulation::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// A request read from stdin, one JSON object per line:
+///
+/// ```text
+/// {"cmd": "reset", "seed": 42}
+/// {"cmd": "step", "action": "move_left"}
+/// {"cmd": "step", "action": {"place": {"rotate_index": 1, "col": 3}}}
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum EnvRequest {
+    /// Starts a new game, with a random seed when none is given.
+    Reset {
+        #[serde(default)]
+        seed: Option<u64>,
+    },
+    Step {
+        action: EnvAction,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EnvAction {
+    /// Drops the piece at one of the observation's `placements`.
+    Place { place: PlaceTarget },
+    /// A single player action, named like in the key bindings.
+    Move(InputAction),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceTarget {
+    pub rotate_index: usize,
+    pub col: i32,
+}
+
+impl From<&Placement> for PlaceTarget {
+    fn from(placement: &Placement) -> Self {
+        Self {
+            rotate_index: placement.index.rotate_index,
+            col: placement.pos.col,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PieceState {
+    pub type_index: usize,
+    pub rotate_index: usize,
+    pub row: i32,
+    pub col: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Observation {
+    /// Board cells from the bottom row up, holding the piece type of each block.
+    pub board: Vec<Vec<Option<usize>>>,
+    /// Rows above these are only reachable while spawning; a block locked there ends
+    /// the game.
+    pub main_rows: usize,
+    /// The falling piece, missing once the game is over.
+    pub piece: Option<PieceState>,
+    pub preview: BoxIndex,
+    pub hold: Option<BoxIndex>,
+    pub can_hold: bool,
+    /// Targets the falling piece can reach, valid for a `place` action.
+    pub placements: Vec<PlaceTarget>,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    pub pieces: u64,
+    pub ticks: u64,
+}
+
+impl Observation {
+    pub fn new(simulation: &Simulation) -> Self {
+        let rules = &simulation.rules;
+        let scoreboard = &rules.scoreboard;
+        Self {
+            board: rules.board.cells().clone(),
+            main_rows: rules.board.main_rows(),
+            piece: rules.piece().map(|(index, pos)| PieceState {
+                type_index: index.type_index,
+                rotate_index: index.rotate_index,
+                row: pos.row,
+                col: pos.col,
+            }),
+            preview: rules.next().clone(),
+            hold: rules.hold().cloned(),
+            can_hold: rules.can_hold(),
+            placements: simulation
+                .placements()
+                .iter()
+                .map(PlaceTarget::from)
+                .collect(),
+            score: scoreboard.score,
+            lines: scoreboard.lines,
+            level: scoreboard.level,
+            pieces: rules.pieces(),
+            ticks: rules.ticks(),
+        }
+    }
+}
+
+/// A reply written to stdout for every request. The reward is the score gained by the
+/// step, and zero after a reset.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EnvResponse {
+    Observation {
+        observation: Observation,
+        reward: u32,
+        done: bool,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// Answers requests on stdin until it is closed. Each step plays one gameplay tick,
+/// followed by the ticks the game takes no input in, such as clearing rows; no clock is
+/// involved, so games run as fast as the requests come in.
+pub fn run_env(args: &Args) -> Result<(), MyError> {
+    let game_lib = GameLib::new(
+        &args.config_path,
+        &mut Assets::default(),
+        &mut Assets::default(),
+        true,
+    )?;
+    let mut simulation: Option<Simulation> = None;
+    let mut stdout = std::io::stdout().lock();
+
+    info!("Environment ready");
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let seed = args.seed.or(game_lib.config.seed);
+        let response = respond(&line, &mut simulation, &game_lib, seed);
+        serde_json::to_writer(&mut stdout, &response)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+
+    info!("Environment closed");
+
+    Ok(())
+}
+
+/// Answers one request line. A reset without a seed uses `seed`, or a random one.
+fn respond<'a>(
+    line: &str,
+    simulation: &mut Option<Simulation<'a>>,
+    game_lib: &'a GameLib,
+    seed: Option<u64>,
+) -> EnvResponse {
+    let response = match serde_json::from_str::<EnvRequest>(line) {
+        Ok(EnvRequest::Reset { seed: requested }) => {
+            let seed = requested.or(seed).unwrap_or_else(rand::random);
+            info!("Environment reset with seed {}", seed);
+            let simulation = simulation.insert(Simulation::new(game_lib, seed));
+            Ok(observe(simulation, 0))
+        }
+        Ok(EnvRequest::Step { action }) => match simulation.as_mut() {
+            Some(simulation) => step(simulation, &action),
+            None => Err("reset the environment before stepping".to_string()),
+        },
+        Err(err) => Err(format!("invalid request: {}", err)),
+    };
+
+    response.unwrap_or_else(|error| {
+        warn!("Environment request failed: {}", error);
+        EnvResponse::Error { error }
+    })
+}
+
+fn step(simulation: &mut Simulation, action: &EnvAction) -> Result<EnvResponse, String> {
+    if simulation.is_over() {
+        return Err("the game is over, reset the environment".to_string());
+    }
+
+    let reward = match action {
+        EnvAction::Move(action) => simulation.apply(*action),
+        EnvAction::Place { place } => {
+            let placement = simulation
+                .placements()
+                .into_iter()
+                .find(|p| PlaceTarget::from(p) == *place)
+                .ok_or_else(|| {
+                    format!(
+                        "placement with rotation {} at column {} is not reachable",
+                        place.rotate_index, place.col
+                    )
+                })?;
+            simulation.place(&placement)
+        }
+    };
+
+    Ok(observe(simulation, reward))
+}
+
+fn observe(simulation: &Simulation, reward: u32) -> EnvResponse {
+    EnvResponse::Observation {
+        observation: Observation::new(simulation),
+        reward,
+        done: simulation.is_over(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_lib() -> GameLib {
+        GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap()
+    }
+
+    fn error(response: EnvResponse) -> String {
+        match response {
+            EnvResponse::Error { error } => error,
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stepping_needs_a_reset() {
+        let game_lib = game_lib();
+        let mut simulation = None;
+        let step = r#"{"cmd": "step", "action": "move_left"}"#;
+
+        let response = respond(step, &mut simulation, &game_lib, Some(7));
+        assert!(error(response).contains("reset"));
+    }
+
+    #[test]
+    fn unreachable_placements_are_reported_by_field() {
+        let game_lib = game_lib();
+        let mut simulation = None;
+        respond(r#"{"cmd": "reset"}"#, &mut simulation, &game_lib, Some(7));
+
+        let place = r#"{"cmd": "step", "action": {"place": {"rotate_index": 9, "col": -4}}}"#;
+        let response = respond(place, &mut simulation, &game_lib, Some(7));
+        assert_eq!(
+            error(response),
+            "placement with rotation 9 at column -4 is not reachable"
+        );
+    }
+
+    #[test]
+    fn moves_return_observations_until_the_game_tops_out() {
+        let game_lib = game_lib();
+        let mut simulation = None;
+        let reset = respond(r#"{"cmd": "reset"}"#, &mut simulation, &game_lib, Some(7));
+        assert!(matches!(
+            reset,
+            EnvResponse::Observation { done: false, .. }
+        ));
+
+        let move_left = r#"{"cmd": "step", "action": "move_left"}"#;
+        match respond(move_left, &mut simulation, &game_lib, Some(7)) {
+            EnvResponse::Observation {
+                observation, done, ..
+            } => {
+                assert!(!done);
+                assert!(observation.piece.is_some());
+                assert_eq!(observation.ticks, 2);
+            }
+            other => panic!("expected an observation, got {:?}", other),
+        }
+
+        let hard_drop = r#"{"cmd": "step", "action": "hard_drop"}"#;
+        let mut done = false;
+        for _ in 0..100 {
+            match respond(hard_drop, &mut simulation, &game_lib, Some(7)) {
+                EnvResponse::Observation {
+                    done: true,
+                    observation,
+                    ..
+                } => {
+                    assert!(observation.piece.is_none());
+                    done = true;
+                    break;
+                }
+                EnvResponse::Observation { .. } => {}
+                other => panic!("expected an observation, got {:?}", other),
+            }
+        }
+        assert!(done);
+
+        let response = respond(hard_drop, &mut simulation, &game_lib, Some(7));
+        assert!(error(response).contains("over"));
+    }
+}
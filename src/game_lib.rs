@@ -1,9 +1,21 @@
+use crate::accessibility::*;
+use crate::audio::*;
+use crate::board::*;
+use crate::bot::*;
+use crate::high_score::*;
+use crate::hint::*;
+use crate::line_clear::*;
+use crate::motion::*;
+use crate::music::*;
 use crate::my_error::*;
 use crate::play_box::*;
+use crate::pointer::*;
+use crate::score::*;
 use crate::utils::*;
+use crate::versus::*;
 use bevy::prelude::*;
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Resource)]
 pub struct GameConfig {
@@ -13,9 +25,41 @@ pub struct GameConfig {
     pub drop_down_interval: f32,
     pub fast_down_interval: f32,
     pub fast_down_max_steps: u32,
-    pub flash_full_line_interval: f32,
-    pub flash_full_line_max_count: u32,
+    pub line_clear_config: LineClearConfig,
+    pub motion_config: MotionConfig,
+    pub collapse_config: CollapseConfig,
+    pub score_config: ScoreConfig,
+    pub audio_config: AudioConfig,
+    pub music_config: MusicConfig,
     pub preview_config: PreviewConfig,
+    pub hold_config: PreviewConfig,
+    pub theme_config: ThemeConfig,
+    #[serde(alias = "key_bindings_path")]
+    pub bindings_path: PathBuf,
+    pub pointer_config: PointerConfig,
+    pub accessibility_config: AccessibilityConfig,
+    pub game_mode: GameMode,
+    pub high_score_config: HighScoreConfig,
+    /// Saved game file name inside the user data directory.
+    pub save_file: String,
+    /// Gameplay ticks per second.
+    pub tick_rate: f64,
+    /// Fixed piece generator seed; a random one is used when missing.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub bot_config: BotConfig,
+    pub hint_config: HintConfig,
+    pub versus_config: VersusConfig,
+}
+
+impl GameConfig {
+    /// Color of a block of the given type before any theme or palette is applied.
+    pub fn box_color(&self, type_index: usize) -> Color {
+        match self.box_config.play_boxes.get(type_index) {
+            Some(play_box) => play_box.color(),
+            None => vec_to_color(&self.versus_config.garbage_color),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,6 +100,12 @@ impl BoxConfig {
     pub fn play_box_bitmap(&self, index: &BoxIndex) -> &BitMap {
         &self.play_boxes[index.type_index].bitmaps[index.rotate_index]
     }
+
+    /// Type of the garbage blocks sent between versus players, which comes after the
+    /// play box types so themes and palettes can style it like them.
+    pub fn garbage_type_index(&self) -> usize {
+        self.play_box_type_count()
+    }
 }
 
 pub const PLAY_BOX_BITMAP_SIZE: usize = 4;
@@ -147,15 +197,27 @@ pub struct PreviewConfig {
     pub border_z: f32,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ThemeConfig {
+    pub dir: PathBuf,
+    pub themes: Vec<String>,
+    pub current: String,
+}
+
 #[derive(Resource, Debug)]
 pub struct GameLib {
     pub config: GameConfig,
+    pub design_size: Vec2,
+    pub scale: f32,
     pub origin_pos: Vec2,
     pub box_span: f32,
     pub box_mesh: Handle<Mesh>,
     pub box_colors: Vec<Handle<ColorMaterial>>,
     pub box_sizes: Vec<Vec<ISize>>,
     pub box_positions: Vec<Vec<Vec<BoxPos>>>,
+    /// Set when running without a window, headless or in the terminal, in which case no
+    /// meshes or materials are created and blocks are spawned without them.
+    pub headless: bool,
 }
 
 impl GameLib {
@@ -163,27 +225,38 @@ impl GameLib {
         path: P,
         meshes: &mut Assets<Mesh>,
         materials: &mut Assets<ColorMaterial>,
+        headless: bool,
     ) -> Result<Self, MyError> {
         let config: GameConfig = read_json(path)?;
         let box_config = &config.box_config;
-        let origin_pos = -Vec2::new(
+        let design_size = Vec2::new(
             config.window_size.width as f32,
             config.window_size.height as f32,
-        ) / 2.0;
+        );
+        let origin_pos = -design_size / 2.0;
         let box_span = box_config.size + box_config.spacing;
-        let box_mesh = meshes.add(Rectangle::new(box_config.size, box_config.size));
-        let box_colors = Self::init_box_colors(&box_config.play_boxes, materials);
+        let (box_mesh, box_colors) = if headless {
+            (Handle::default(), Vec::new())
+        } else {
+            (
+                meshes.add(Rectangle::new(box_config.size, box_config.size)),
+                Self::init_box_colors(&config, materials),
+            )
+        };
         let box_sizes = Self::init_box_sizes(&box_config.play_boxes);
         let box_positions = Self::init_box_positions(&box_config.play_boxes);
 
         let game_lib = GameLib {
             config,
+            design_size,
+            scale: 1.0,
             origin_pos,
             box_span,
             box_mesh,
             box_colors,
             box_sizes,
             box_positions,
+            headless,
         };
 
         info!("GameLib initialized");
@@ -191,6 +264,45 @@ impl GameLib {
         Ok(game_lib)
     }
 
+    pub fn update_layout(&mut self, window_size: Vec2, meshes: &mut Assets<Mesh>) {
+        let box_config = &self.config.box_config;
+        let scale = (window_size / self.design_size).min_element();
+
+        if scale <= 0.0 {
+            return;
+        }
+
+        self.scale = scale;
+        self.origin_pos = -self.design_size * scale / 2.0;
+        self.box_span = self.design_box_span() * scale;
+
+        if let Some(mesh) = meshes.get_mut(&self.box_mesh) {
+            let size = box_config.size * scale;
+            *mesh = Rectangle::new(size, size).into();
+        }
+
+        info!(
+            "Layout updated: window={:?} scale={} box_span={}",
+            window_size, self.scale, self.box_span
+        );
+    }
+
+    /// Replaces the design size of the window, e.g. to fit a second board.
+    pub fn set_design_size(&mut self, size: Vec2) {
+        self.design_size = size;
+        self.origin_pos = -self.design_size * self.scale / 2.0;
+    }
+
+    #[inline]
+    pub fn layout_pos(&self, design_pos: &Vec2) -> Vec2 {
+        self.origin_pos + *design_pos * self.scale
+    }
+
+    #[inline]
+    pub fn design_box_span(&self) -> f32 {
+        self.config.box_config.size + self.config.box_config.spacing
+    }
+
     pub fn box_size(&self, index: &BoxIndex) -> &ISize {
         &self.box_sizes[index.type_index][index.rotate_index]
     }
@@ -199,13 +311,14 @@ impl GameLib {
         &self.box_positions[index.type_index][index.rotate_index]
     }
 
+    /// Materials of the play box types, followed by the one of garbage.
     fn init_box_colors(
-        play_boxes: &[PlayBoxConfig],
+        config: &GameConfig,
         materials: &mut Assets<ColorMaterial>,
     ) -> Vec<Handle<ColorMaterial>> {
         let mut colors: Vec<Handle<ColorMaterial>> = Vec::new();
-        for b in play_boxes.iter() {
-            let material = materials.add(b.color());
+        for type_index in 0..=config.box_config.garbage_type_index() {
+            let material = materials.add(config.box_color(type_index));
             colors.push(material);
         }
         colors
@@ -0,0 +1,59 @@
+use crate::replay::*;
+use crate::rules::*;
+use crate::script::*;
+use crate::systems::*;
+use bevy::prelude::*;
+use serde::Serialize;
+
+/// Outcome of a game, printed as a JSON line when running headless.
+#[derive(Debug, Serialize, Clone)]
+pub struct GameResult {
+    pub seed: u64,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    pub pieces: u64,
+    pub ticks: u64,
+    /// Seconds of game time played.
+    pub elapsed: f32,
+    /// Board assertions of the input script that failed, if one was run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_checks: Option<usize>,
+}
+
+impl GameResult {
+    pub fn new(rules: &GameRules, clock: &GameClock) -> Self {
+        let scoreboard = &rules.scoreboard;
+        Self {
+            seed: rules.index_gen().seed(),
+            score: scoreboard.score,
+            lines: scoreboard.lines,
+            level: scoreboard.level,
+            pieces: rules.pieces(),
+            ticks: clock.tick,
+            elapsed: scoreboard.elapsed,
+            failed_checks: None,
+        }
+    }
+}
+
+pub fn report_result(
+    game: Res<Game>,
+    clock: Res<GameClock>,
+    checks: Option<Res<ScriptChecks>>,
+    mut exit_app: MessageWriter<AppExit>,
+) {
+    let mut result = GameResult::new(&game, clock.as_ref());
+    result.failed_checks = checks.map(|c| c.failed);
+    info!("Game finished {:?}", result);
+
+    match serde_json::to_string(&result) {
+        Ok(line) => println!("{}", line),
+        Err(err) => error!("Failed to serialize game result: {}", err),
+    }
+    exit_app.write(if result.failed_checks.is_some_and(|n| n > 0) {
+        AppExit::error()
+    } else {
+        AppExit::Success
+    });
+}
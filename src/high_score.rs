@@ -0,0 +1,345 @@
+use crate::game_lib::*;
+use crate::my_error::MyError;
+use crate::score::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::{
+    input::{ButtonState, keyboard::Key, keyboard::KeyboardInput},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct HighScoreConfig {
+    /// File name inside the user data directory.
+    pub file_name: String,
+    pub max_entries: usize,
+    pub max_name_len: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// Seconds played.
+    pub duration: f32,
+    pub date: String,
+}
+
+/// Best scores per game mode, sorted from highest to lowest.
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    #[serde(skip)]
+    path: PathBuf,
+    tables: HashMap<GameMode, Vec<HighScoreEntry>>,
+}
+
+impl HighScores {
+    /// Loads the table from `path`. A missing file gives an empty table, while a file
+    /// that cannot be parsed is reported as [`MyError::CorruptedHighScores`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::fresh(path));
+        }
+
+        let mut high_scores: HighScores = read_json(path).map_err(|err| {
+            MyError::CorruptedHighScores(path.display().to_string(), err.to_string())
+        })?;
+        high_scores.path = path.to_path_buf();
+
+        info!("High scores loaded from {}", path.display());
+
+        Ok(high_scores)
+    }
+
+    /// Starts an empty table in place of a file that could not be loaded. The file is
+    /// first moved aside with a `.bak` suffix, so saving the new table does not destroy
+    /// the old scores.
+    pub fn recover<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".{}.bak", unix_time()));
+        let backup = PathBuf::from(backup);
+
+        match fs::rename(path, &backup) {
+            Ok(()) => warn!("Moved unreadable high scores to {}", backup.display()),
+            Err(err) => error!("Failed to back up high scores {}: {}", path.display(), err),
+        }
+
+        Self::fresh(path)
+    }
+
+    pub fn fresh<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), MyError> {
+        write_json(self, &self.path)?;
+        info!("High scores saved to {}", self.path.display());
+        Ok(())
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScoreEntry] {
+        self.tables.get(&mode).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn qualifies(&self, mode: GameMode, score: u32, max_entries: usize) -> bool {
+        let table = self.table(mode);
+        score > 0 && (table.len() < max_entries || table.last().is_some_and(|e| score > e.score))
+    }
+
+    /// Inserts an entry keeping the table sorted and trimmed, and returns its rank.
+    pub fn insert(&mut self, mode: GameMode, entry: HighScoreEntry, max_entries: usize) -> usize {
+        let table = self.tables.entry(mode).or_default();
+        let rank = table
+            .iter()
+            .position(|e| entry.score > e.score)
+            .unwrap_or(table.len());
+        table.insert(rank, entry);
+        table.truncate(max_entries);
+        rank
+    }
+}
+
+/// What the high score panel currently shows.
+#[derive(Resource, Debug, Default)]
+pub struct HighScoreScreen {
+    pub visible: bool,
+    pub game_over: bool,
+    /// Name typed so far while a new record is being entered.
+    pub name_entry: Option<String>,
+    /// Rank of the entry added last, highlighted in the table.
+    pub highlight: Option<usize>,
+}
+
+#[derive(Component)]
+pub struct HighScorePanel;
+
+#[derive(Component)]
+pub struct HighScoreText;
+
+pub fn high_score_path(game_lib: &GameLib) -> PathBuf {
+    user_data_dir().join(&game_lib.config.high_score_config.file_name)
+}
+
+pub fn spawn_high_score_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: percent(100),
+                height: percent(100),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            HighScorePanel,
+        ))
+        .with_child((
+            Node {
+                padding: UiRect::all(px(16)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            children![(
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                HighScoreText,
+            )],
+        ));
+}
+
+pub fn show_game_over_scores(
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    high_scores: Res<HighScores>,
+    mut screen: ResMut<HighScoreScreen>,
+) {
+    let config = &game_lib.config;
+    screen.visible = true;
+    screen.game_over = true;
+    screen.highlight = None;
+
+    if high_scores.qualifies(
+        config.game_mode,
+        game.scoreboard.score,
+        config.high_score_config.max_entries,
+    ) {
+        info!("New high score {}", game.scoreboard.score);
+        screen.name_entry = Some(String::new());
+    }
+}
+
+pub fn toggle_high_scores(keys: Res<ButtonInput<KeyCode>>, mut screen: ResMut<HighScoreScreen>) {
+    if keys.just_pressed(KeyCode::F6) && screen.name_entry.is_none() {
+        screen.visible = !screen.visible;
+    }
+}
+
+pub fn enter_high_score_name(
+    mut keyboard_messages: MessageReader<KeyboardInput>,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    mut high_scores: ResMut<HighScores>,
+    mut screen: ResMut<HighScoreScreen>,
+) {
+    let config = &game_lib.config;
+    let Some(mut name) = screen.name_entry.clone() else {
+        keyboard_messages.clear();
+        return;
+    };
+
+    let mut submit = false;
+    for message in keyboard_messages.read() {
+        if message.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match &message.logical_key {
+            Key::Enter => submit = true,
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Character(s) => {
+                for c in s.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < config.high_score_config.max_name_len {
+                        name.push(c);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !submit {
+        if screen.name_entry.as_ref() != Some(&name) {
+            screen.name_entry = Some(name);
+        }
+        return;
+    }
+
+    let name = name.trim();
+    let entry = HighScoreEntry {
+        name: if name.is_empty() { "Player" } else { name }.to_string(),
+        score: game.scoreboard.score,
+        lines: game.scoreboard.lines,
+        level: game.scoreboard.level,
+        duration: game.scoreboard.elapsed,
+        date: format_date(unix_time()),
+    };
+    let rank = high_scores.insert(
+        config.game_mode,
+        entry,
+        config.high_score_config.max_entries,
+    );
+    if let Err(err) = high_scores.save() {
+        error!("Failed to save high scores: {}", err);
+    }
+
+    screen.name_entry = None;
+    screen.highlight = Some(rank);
+}
+
+pub fn update_high_score_panel(
+    game_lib: Res<GameLib>,
+    high_scores: Res<HighScores>,
+    screen: Res<HighScoreScreen>,
+    mut panel: Single<&mut Visibility, With<HighScorePanel>>,
+    mut text: Single<&mut Text, With<HighScoreText>>,
+) {
+    **panel = if screen.visible {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    if screen.game_over {
+        lines.push("GAME OVER".to_string());
+    }
+    if let Some(name) = &screen.name_entry {
+        lines.push(format!("New record! Enter your name: {}_", name));
+    }
+    lines.push(format!("High scores ({:?})", game_lib.config.game_mode));
+    lines.push(format!(
+        "{:>3}  {:<12} {:>8} {:>6} {:>6} {:>8}  {}",
+        "#", "Name", "Score", "Lines", "Level", "Time", "Date"
+    ));
+
+    for (i, e) in high_scores
+        .table(game_lib.config.game_mode)
+        .iter()
+        .enumerate()
+    {
+        let marker = if screen.highlight == Some(i) {
+            ">"
+        } else {
+            " "
+        };
+        let secs = e.duration as u32;
+        lines.push(format!(
+            "{}{:>2}  {:<12} {:>8} {:>6} {:>6} {:>5}:{:02}  {}",
+            marker,
+            i + 1,
+            e.name,
+            e.score,
+            e.lines,
+            e.level,
+            secs / 60,
+            secs % 60,
+            e.date
+        ));
+    }
+
+    text.0 = lines.join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recover_backs_up_unreadable_file() {
+        let dir = std::env::temp_dir().join(format!("high-score-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("high_scores.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        assert!(matches!(
+            HighScores::load(&path),
+            Err(MyError::CorruptedHighScores(..))
+        ));
+        let high_scores = HighScores::recover(&path);
+        high_scores.save().unwrap();
+
+        let backups: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join(&backups[0])).unwrap(),
+            "{ not json"
+        );
+        assert!(HighScores::load(&path).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
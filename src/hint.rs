@@ -0,0 +1,131 @@
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::rules::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HintConfig {
+    pub enabled: bool,
+    /// Evaluator the recommended placements are scored with.
+    pub weights: BotWeights,
+    /// Outline of the recommended placement of the current piece.
+    pub color: [u8; 4],
+    /// Outline of the recommended placement of the next piece.
+    pub next_color: [u8; 4],
+}
+
+/// Recommended placements of the current and the next piece, drawn as outlines on the
+/// board. Toggled with F8.
+#[derive(Resource, Debug)]
+pub struct Hint {
+    config: HintConfig,
+    pub enabled: bool,
+    /// Pieces spawned and whether holding was allowed when the hint was computed. A new
+    /// piece changes the count and swapping with the hold slot forbids holding, so a
+    /// change means it has to be computed again.
+    computed_for: Option<(u64, bool)>,
+    pub current: Option<Placement>,
+    pub next: Option<Placement>,
+}
+
+impl Hint {
+    pub fn new(config: &HintConfig) -> Self {
+        Self {
+            config: config.clone(),
+            enabled: config.enabled,
+            computed_for: None,
+            current: None,
+            next: None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.computed_for = None;
+        self.current = None;
+        self.next = None;
+    }
+
+    /// Picks the placement of the current piece that leaves the best board after the
+    /// next piece is placed too, and the best placement of the next piece after that.
+    fn compute(&mut self, rules: &GameRules, game_lib: &GameLib) {
+        let (board, next) = (&rules.board, Some(rules.next()));
+        let weights = &self.config.weights;
+        self.current = rules
+            .piece()
+            .and_then(|(index, pos)| best_placement(board, index, pos, next, game_lib, weights));
+        self.next = self.current.as_ref().zip(next).and_then(|(current, next)| {
+            let (after, _) = current.apply(board, game_lib);
+            let pos = init_box_pos(&after, next, game_lib)?;
+            best_placement(&after, next, &pos, None, game_lib, weights)
+        });
+    }
+}
+
+pub fn toggle_hint(keys: Res<ButtonInput<KeyCode>>, mut hint: ResMut<Hint>) {
+    if keys.just_pressed(KeyCode::F8) {
+        hint.enabled = !hint.enabled;
+        info!("Placement hint {}", if hint.enabled { "on" } else { "off" });
+    }
+}
+
+/// Recomputes the hint whenever a new piece spawns.
+pub fn update_hint(game_lib: Res<GameLib>, game: Res<Game>, mut hint: ResMut<Hint>) {
+    if !hint.enabled || game.piece().is_none() {
+        if hint.computed_for.is_some() {
+            hint.clear();
+        }
+        return;
+    }
+
+    let spawned = Some((game.pieces(), game.can_hold()));
+    if hint.computed_for == spawned {
+        return;
+    }
+
+    hint.compute(&game, game_lib.as_ref());
+    hint.computed_for = spawned;
+}
+
+pub fn draw_hint(
+    mut gizmos: Gizmos,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    game_panel: Res<GamePanel>,
+    hint: Res<Hint>,
+) {
+    if !hint.enabled {
+        return;
+    }
+
+    let size = Vec2::splat(game_lib.config.box_config.size * game_lib.scale);
+    let targets = [
+        (&hint.current, &hint.config.color),
+        (&hint.next, &hint.config.next_color),
+    ];
+
+    for (placement, color) in targets {
+        let Some(placement) = placement else {
+            continue;
+        };
+
+        let color = vec_to_color(color);
+        for pos in game_lib.box_pos(&placement.index) {
+            let (row, col) = (placement.pos.row + pos.row, placement.pos.col + pos.col);
+            if row >= game.board.main_rows() as i32 {
+                continue;
+            }
+
+            let center = get_box_pos(
+                &game_panel.play_region.box_origin,
+                row,
+                col,
+                game_lib.box_span,
+            );
+            gizmos.rect_2d(Isometry2d::from_translation(center), size, color);
+        }
+    }
+}
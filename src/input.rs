@@ -0,0 +1,686 @@
+use crate::my_error::MyError;
+use crate::replay::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    path::Path,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+    MoveLeft,
+    MoveRight,
+    RotateCw,
+    RotateCcw,
+    #[serde(rename = "rotate_180")]
+    Rotate180,
+    SoftDrop,
+    HardDrop,
+    Hold,
+    Pause,
+    Save,
+}
+
+impl InputAction {
+    /// Actions that may be left without a key. Saving came after the first bindings
+    /// files, which get [`DEFAULT_SAVE_KEY`] instead.
+    pub fn is_optional(&self) -> bool {
+        matches!(self, InputAction::Save)
+    }
+
+    pub const ALL: [InputAction; 10] = [
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::RotateCw,
+        InputAction::RotateCcw,
+        InputAction::Rotate180,
+        InputAction::SoftDrop,
+        InputAction::HardDrop,
+        InputAction::Hold,
+        InputAction::Pause,
+        InputAction::Save,
+    ];
+}
+
+/// Key bound to the save action when a bindings file does not mention it.
+pub const DEFAULT_SAVE_KEY: &str = "F7";
+
+/// Left stick directions that can be bound like buttons once the stick is pushed
+/// past the configured dead zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StickDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadControl {
+    Button(GamepadButton),
+    Stick(StickDirection),
+}
+
+impl GamepadControl {
+    pub fn is_pressed(&self, gamepad: &Gamepad, dead_zone: f32) -> bool {
+        match self {
+            GamepadControl::Button(button) => gamepad.pressed(*button),
+            GamepadControl::Stick(direction) => {
+                let stick = gamepad.left_stick();
+                match direction {
+                    StickDirection::Left => stick.x < -dead_zone,
+                    StickDirection::Right => stick.x > dead_zone,
+                    StickDirection::Up => stick.y > dead_zone,
+                    StickDirection::Down => stick.y < -dead_zone,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RepeatConfig {
+    /// Seconds an action has to be held before it starts repeating.
+    pub delay: f32,
+    /// Seconds between repeats once the delay has passed.
+    pub interval: f32,
+    pub actions: Vec<InputAction>,
+}
+
+impl Default for RepeatConfig {
+    fn default() -> Self {
+        Self {
+            delay: 0.17,
+            interval: 0.05,
+            actions: vec![
+                InputAction::MoveLeft,
+                InputAction::MoveRight,
+                InputAction::SoftDrop,
+            ],
+        }
+    }
+}
+
+impl RepeatConfig {
+    /// Returns whether a repeat fires while the hold time of an action advances from
+    /// `before` to `after`.
+    fn fires(&self, before: f32, after: f32) -> bool {
+        if after < self.delay {
+            return false;
+        }
+        if before < self.delay || self.interval <= 0.0 {
+            return true;
+        }
+        ((after - self.delay) / self.interval).floor()
+            > ((before - self.delay) / self.interval).floor()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BindingsFile {
+    keyboard: HashMap<InputAction, Vec<String>>,
+    #[serde(default)]
+    gamepad: HashMap<InputAction, Vec<String>>,
+    #[serde(default = "default_stick_dead_zone")]
+    stick_dead_zone: f32,
+    #[serde(default)]
+    repeat: RepeatConfig,
+}
+
+fn default_stick_dead_zone() -> f32 {
+    0.5
+}
+
+#[derive(Resource, Debug)]
+pub struct InputBindings {
+    keys: HashMap<InputAction, Vec<KeyCode>>,
+    gamepad: HashMap<InputAction, Vec<GamepadControl>>,
+    pub stick_dead_zone: f32,
+    pub repeat: RepeatConfig,
+}
+
+impl InputBindings {
+    /// Loads bindings from a JSON file whose `keyboard` and `gamepad` sections map
+    /// action names to lists of control names, e.g. `{ "move_left": ["ArrowLeft"] }`.
+    /// Key names are those of [`KeyCode`] variants, gamepad names those of
+    /// [`GamepadButton`] variants plus `LeftStickLeft`, `LeftStickRight`,
+    /// `LeftStickUp` and `LeftStickDown`. Every action but save needs at least one
+    /// key, while gamepad bindings are optional.
+    ///
+    /// Key bindings files from before gamepad support, a single object mapping actions
+    /// to key names, are still accepted and get the default stick and repeat settings.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let value: serde_json::Value = read_json(path)?;
+        Self::from_json(value)
+    }
+
+    pub fn from_json(value: serde_json::Value) -> Result<Self, MyError> {
+        let file: BindingsFile = if value.get("keyboard").is_some() {
+            serde_json::from_value(value)?
+        } else {
+            warn!("Converting key bindings from the keyboard-only format");
+            BindingsFile {
+                keyboard: serde_json::from_value(value)?,
+                gamepad: HashMap::new(),
+                stick_dead_zone: default_stick_dead_zone(),
+                repeat: RepeatConfig::default(),
+            }
+        };
+        let mut keyboard = file.keyboard;
+        if !keyboard.contains_key(&InputAction::Save) {
+            let taken = keyboard.values().flatten().any(|k| k == DEFAULT_SAVE_KEY);
+            let keys = if taken {
+                warn!("{} is taken, the save action has no key", DEFAULT_SAVE_KEY);
+                Vec::new()
+            } else {
+                vec![DEFAULT_SAVE_KEY.to_string()]
+            };
+            keyboard.insert(InputAction::Save, keys);
+        }
+
+        let keys = Self::parse_controls(&keyboard, true, parse_key_code, MyError::InvalidKey)?;
+        let gamepad = Self::parse_controls(
+            &file.gamepad,
+            false,
+            parse_gamepad_control,
+            MyError::InvalidGamepadControl,
+        )?;
+
+        info!("Input bindings loaded");
+
+        Ok(Self {
+            keys,
+            gamepad,
+            stick_dead_zone: file.stick_dead_zone,
+            repeat: file.repeat,
+        })
+    }
+
+    pub fn keys(&self, action: InputAction) -> &[KeyCode] {
+        self.keys.get(&action).map(|k| k.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn gamepad_controls(&self, action: InputAction) -> &[GamepadControl] {
+        self.gamepad
+            .get(&action)
+            .map(|c| c.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Actions held down on the keyboard or on any of the given gamepads.
+    pub fn pressed_actions<'a>(
+        &self,
+        keys: &ButtonInput<KeyCode>,
+        gamepads: impl IntoIterator<Item = &'a Gamepad>,
+    ) -> HashSet<InputAction> {
+        let mut pressed: HashSet<InputAction> = InputAction::ALL
+            .into_iter()
+            .filter(|action| keys.any_pressed(self.keys(*action).iter().copied()))
+            .collect();
+
+        for gamepad in gamepads {
+            for action in InputAction::ALL {
+                if self
+                    .gamepad_controls(action)
+                    .iter()
+                    .any(|c| c.is_pressed(gamepad, self.stick_dead_zone))
+                {
+                    pressed.insert(action);
+                }
+            }
+        }
+
+        pressed
+    }
+
+    fn parse_controls<T: Copy + Eq + Hash>(
+        names: &HashMap<InputAction, Vec<String>>,
+        required: bool,
+        parse: fn(&str) -> Option<T>,
+        invalid: fn(String) -> MyError,
+    ) -> Result<HashMap<InputAction, Vec<T>>, MyError> {
+        let mut controls: HashMap<InputAction, Vec<T>> = HashMap::new();
+        let mut used: HashMap<T, InputAction> = HashMap::new();
+
+        for action in InputAction::ALL {
+            let control_names = names.get(&action).map(|n| n.as_slice()).unwrap_or(&[]);
+            if required && !action.is_optional() && control_names.is_empty() {
+                return Err(MyError::MissingBinding(format!("{:?}", action)));
+            }
+
+            let mut parsed: Vec<T> = Vec::new();
+            for name in control_names.iter() {
+                let Some(control) = parse(name) else {
+                    return Err(invalid(name.clone()));
+                };
+
+                if let Some(other) = used.insert(control, action)
+                    && other != action
+                {
+                    return Err(MyError::ConflictingBinding(
+                        name.clone(),
+                        format!("{:?}", other),
+                        format!("{:?}", action),
+                    ));
+                }
+                parsed.push(control);
+            }
+            controls.insert(action, parsed);
+        }
+
+        Ok(controls)
+    }
+}
+
+/// Actions requested by the player this frame, independent of the input device.
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    pressed: HashSet<InputAction>,
+    just_pressed: HashSet<InputAction>,
+    triggered: HashSet<InputAction>,
+    held: HashMap<InputAction, f32>,
+    target_col: Option<i32>,
+}
+
+impl ActionState {
+    /// Advances the state by `delta` seconds given the actions currently held down.
+    /// An action triggers when it is pressed and, if it is listed in `repeat`, again
+    /// while it stays held.
+    pub fn update(&mut self, pressed: HashSet<InputAction>, delta: f32, repeat: &RepeatConfig) {
+        self.just_pressed = pressed.difference(&self.pressed).copied().collect();
+        self.triggered = self.just_pressed.clone();
+        self.target_col = None;
+        self.held.retain(|action, _| pressed.contains(action));
+
+        for action in pressed.iter() {
+            let held = self.held.entry(*action).or_insert(0.0);
+            if self.just_pressed.contains(action) {
+                continue;
+            }
+
+            let before = *held;
+            *held += delta;
+            if repeat.actions.contains(action) && repeat.fires(before, *held) {
+                self.triggered.insert(*action);
+            }
+        }
+
+        self.pressed = pressed;
+    }
+
+    #[inline]
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// Returns true on the frame an action is pressed and on each auto-repeat.
+    #[inline]
+    pub fn triggered(&self, action: InputAction) -> bool {
+        self.triggered.contains(&action)
+    }
+
+    /// Gameplay actions triggered this frame, in a fixed order. Pause and save are
+    /// handled outside the simulation and left out.
+    pub fn frame(&self) -> ActionFrame {
+        ActionFrame {
+            actions: InputAction::ALL
+                .into_iter()
+                .filter(|a| !matches!(a, InputAction::Pause | InputAction::Save))
+                .filter(|a| self.triggered(*a))
+                .collect(),
+            target_col: self.target_col,
+        }
+    }
+
+    /// Triggers an action for this frame only, e.g. from a pointer gesture.
+    pub fn trigger(&mut self, action: InputAction) {
+        self.triggered.insert(action);
+    }
+
+    /// Sets the column of the game panel the player pointed at this frame.
+    pub fn set_target_col(&mut self, col: i32) {
+        self.target_col = Some(col);
+    }
+}
+
+pub fn read_input(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    bindings: Res<InputBindings>,
+    mut actions: ResMut<ActionState>,
+) {
+    let pressed = bindings.pressed_actions(keys.as_ref(), gamepads.iter());
+    actions.update(pressed, time.delta_secs(), &bindings.repeat);
+}
+
+macro_rules! parse_variant {
+    ($name:expr, $ty:ident, $($variant:ident),* $(,)?) => {
+        match $name {
+            $(stringify!($variant) => Some($ty::$variant),)*
+            _ => None,
+        }
+    };
+}
+
+pub fn parse_key_code(name: &str) -> Option<KeyCode> {
+    parse_variant!(
+        name,
+        KeyCode,
+        KeyA,
+        KeyB,
+        KeyC,
+        KeyD,
+        KeyE,
+        KeyF,
+        KeyG,
+        KeyH,
+        KeyI,
+        KeyJ,
+        KeyK,
+        KeyL,
+        KeyM,
+        KeyN,
+        KeyO,
+        KeyP,
+        KeyQ,
+        KeyR,
+        KeyS,
+        KeyT,
+        KeyU,
+        KeyV,
+        KeyW,
+        KeyX,
+        KeyY,
+        KeyZ,
+        Digit0,
+        Digit1,
+        Digit2,
+        Digit3,
+        Digit4,
+        Digit5,
+        Digit6,
+        Digit7,
+        Digit8,
+        Digit9,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadSubtract,
+        NumpadMultiply,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadEnter,
+        ArrowLeft,
+        ArrowRight,
+        ArrowUp,
+        ArrowDown,
+        Space,
+        Enter,
+        Escape,
+        Tab,
+        Backspace,
+        Delete,
+        Insert,
+        Home,
+        End,
+        PageUp,
+        PageDown,
+        ShiftLeft,
+        ShiftRight,
+        ControlLeft,
+        ControlRight,
+        AltLeft,
+        AltRight,
+        Comma,
+        Period,
+        Slash,
+        Semicolon,
+        Quote,
+        BracketLeft,
+        BracketRight,
+        Backslash,
+        Minus,
+        Equal,
+        Backquote,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+    )
+}
+
+pub fn parse_gamepad_control(name: &str) -> Option<GamepadControl> {
+    let direction = match name {
+        "LeftStickLeft" => Some(StickDirection::Left),
+        "LeftStickRight" => Some(StickDirection::Right),
+        "LeftStickUp" => Some(StickDirection::Up),
+        "LeftStickDown" => Some(StickDirection::Down),
+        _ => None,
+    };
+    if let Some(direction) = direction {
+        return Some(GamepadControl::Stick(direction));
+    }
+
+    parse_variant!(
+        name,
+        GamepadButton,
+        South,
+        East,
+        North,
+        West,
+        C,
+        Z,
+        LeftTrigger,
+        LeftTrigger2,
+        RightTrigger,
+        RightTrigger2,
+        Select,
+        Start,
+        Mode,
+        LeftThumb,
+        RightThumb,
+        DPadUp,
+        DPadDown,
+        DPadLeft,
+        DPadRight,
+    )
+    .map(GamepadControl::Button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn bindings() -> InputBindings {
+        InputBindings::from_json(json!({
+            "keyboard": {
+                "move_left": ["ArrowLeft"],
+                "move_right": ["ArrowRight"],
+                "rotate_cw": ["ArrowUp"],
+                "rotate_ccw": ["KeyZ"],
+                "rotate_180": ["KeyC"],
+                "soft_drop": ["ArrowDown"],
+                "hard_drop": ["Space"],
+                "hold": ["ShiftLeft"],
+                "pause": ["Escape"],
+                "save": ["F7"]
+            },
+            "gamepad": {
+                "move_left": ["DPadLeft", "LeftStickLeft"],
+                "hard_drop": ["West"]
+            },
+            "stick_dead_zone": 0.5,
+            "repeat": { "delay": 0.2, "interval": 0.1, "actions": ["move_left"] }
+        }))
+        .unwrap()
+    }
+
+    /// Runs `read_input` for `frames` frames of `delta` seconds with one gamepad connected.
+    fn read_frames(gamepad: Gamepad, frames: usize, delta: f32) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ActionState>()
+            .insert_resource(bindings())
+            .add_systems(Update, read_input);
+        app.world_mut().spawn(gamepad);
+
+        for _ in 0..frames {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(delta));
+            app.update();
+        }
+        app
+    }
+
+    #[test]
+    fn gamepad_buttons_and_stick_trigger_actions() {
+        let mut gamepad = Gamepad::default();
+        gamepad.digital_mut().press(GamepadButton::West);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickX, -0.8);
+
+        let app = read_frames(gamepad, 1, 0.016);
+        let actions = app.world().resource::<ActionState>();
+
+        assert!(actions.just_pressed(InputAction::HardDrop));
+        assert!(actions.just_pressed(InputAction::MoveLeft));
+        assert!(!actions.just_pressed(InputAction::MoveRight));
+    }
+
+    #[test]
+    fn stick_inside_dead_zone_is_ignored() {
+        let mut gamepad = Gamepad::default();
+        gamepad.analog_mut().set(GamepadAxis::LeftStickX, -0.3);
+
+        let app = read_frames(gamepad, 1, 0.016);
+
+        assert!(
+            !app.world()
+                .resource::<ActionState>()
+                .just_pressed(InputAction::MoveLeft)
+        );
+    }
+
+    #[test]
+    fn held_gamepad_button_repeats_after_delay() {
+        let dpad_left = || {
+            let mut gamepad = Gamepad::default();
+            gamepad.digital_mut().press(GamepadButton::DPadLeft);
+            gamepad
+        };
+
+        // Pressed on the first frame, then held for 0.18s: still inside the delay.
+        let app = read_frames(dpad_left(), 4, 0.06);
+        assert!(
+            !app.world()
+                .resource::<ActionState>()
+                .triggered(InputAction::MoveLeft)
+        );
+
+        // Held for 0.24s, past the delay.
+        let app = read_frames(dpad_left(), 5, 0.06);
+        assert!(
+            app.world()
+                .resource::<ActionState>()
+                .triggered(InputAction::MoveLeft)
+        );
+    }
+
+    #[test]
+    fn keyboard_only_bindings_files_are_converted() {
+        let bindings = InputBindings::from_json(json!({
+            "move_left": ["ArrowLeft", "KeyA"],
+            "move_right": ["ArrowRight", "KeyD"],
+            "rotate_cw": ["ArrowUp"],
+            "rotate_ccw": ["KeyZ"],
+            "rotate_180": ["KeyC"],
+            "soft_drop": ["ArrowDown"],
+            "hard_drop": ["Space"],
+            "hold": ["ShiftLeft"],
+            "pause": ["Escape"],
+            "save": ["F7"]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            bindings.keys(InputAction::MoveLeft),
+            [KeyCode::ArrowLeft, KeyCode::KeyA]
+        );
+        assert!(bindings.gamepad_controls(InputAction::MoveLeft).is_empty());
+        assert_eq!(bindings.stick_dead_zone, 0.5);
+        assert!(bindings.repeat.actions.contains(&InputAction::MoveLeft));
+    }
+
+    #[test]
+    fn bindings_without_save_get_default_key() {
+        let mut keyboard = json!({
+            "move_left": ["ArrowLeft"],
+            "move_right": ["ArrowRight"],
+            "rotate_cw": ["ArrowUp"],
+            "rotate_ccw": ["KeyZ"],
+            "rotate_180": ["KeyC"],
+            "soft_drop": ["ArrowDown"],
+            "hard_drop": ["Space"],
+            "hold": ["ShiftLeft"],
+            "pause": ["Escape"]
+        });
+        let bindings = InputBindings::from_json(keyboard.clone()).unwrap();
+        assert_eq!(bindings.keys(InputAction::Save), [KeyCode::F7]);
+
+        keyboard["pause"] = json!(["Escape", "F7"]);
+        let bindings = InputBindings::from_json(keyboard).unwrap();
+        assert!(bindings.keys(InputAction::Save).is_empty());
+        assert_eq!(
+            bindings.keys(InputAction::Pause),
+            [KeyCode::Escape, KeyCode::F7]
+        );
+    }
+
+    #[test]
+    fn unknown_gamepad_control_is_reported() {
+        let result = InputBindings::from_json(json!({
+            "keyboard": {
+                "move_left": ["ArrowLeft"],
+                "move_right": ["ArrowRight"],
+                "rotate_cw": ["ArrowUp"],
+                "rotate_ccw": ["KeyZ"],
+                "rotate_180": ["KeyC"],
+                "soft_drop": ["ArrowDown"],
+                "hard_drop": ["Space"],
+                "hold": ["ShiftLeft"],
+                "pause": ["Escape"],
+                "save": ["F7"]
+            },
+            "gamepad": { "hold": ["LeftBumper"] }
+        }));
+
+        assert!(
+            matches!(result, Err(MyError::InvalidGamepadControl(name)) if name == "LeftBumper")
+        );
+    }
+}
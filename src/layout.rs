@@ -0,0 +1,76 @@
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::motion::*;
+use crate::play_box::*;
+use crate::preview::*;
+use crate::utils::*;
+use bevy::{prelude::*, window::WindowResized};
+
+/// Position of a panel rectangle in design space, i.e. relative to the bottom-left
+/// corner of the `window_size` area from the config.
+#[derive(Component, Debug, Clone)]
+pub struct LayoutAnchor(pub Vec2);
+
+impl LayoutAnchor {
+    pub fn apply(&self, transform: &mut Transform, game_lib: &GameLib) {
+        let pos = game_lib.layout_pos(&self.0);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        transform.scale = Vec3::new(game_lib.scale, game_lib.scale, 1.0);
+    }
+}
+
+pub fn create_anchored_rect(
+    anchor: Vec2,
+    z: f32,
+    size: &RectSize,
+    color: Color,
+    game_lib: &GameLib,
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) -> Entity {
+    let anchor = LayoutAnchor(anchor);
+    let mut transform = Transform::from_xyz(0.0, 0.0, z);
+    anchor.apply(&mut transform, game_lib);
+
+    let pos = transform.translation.truncate();
+    let entity = create_rect(&pos, z, size, color, commands, meshes, materials);
+    commands.entity(entity).insert((transform, anchor));
+
+    entity
+}
+
+pub fn update_layout(
+    mut resize_events: MessageReader<WindowResized>,
+    mut commands: Commands,
+    mut game_lib: ResMut<GameLib>,
+    mut game_panel: ResMut<GamePanel>,
+    mut preview: ResMut<Preview>,
+    mut hold_slot: ResMut<HoldSlot>,
+    play_box: Res<PlayBox>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut anchors: Query<(&LayoutAnchor, &mut Transform)>,
+) {
+    let Some(event) = resize_events.read().last() else {
+        return;
+    };
+
+    game_lib.update_layout(Vec2::new(event.width, event.height), meshes.as_mut());
+
+    for (anchor, mut transform) in anchors.iter_mut() {
+        anchor.apply(transform.as_mut(), game_lib.as_ref());
+    }
+
+    game_panel.update_layout(&mut commands, game_lib.as_ref());
+    preview.update_layout(&mut commands, game_lib.as_ref());
+    hold_slot
+        .preview
+        .update_layout(&mut commands, game_lib.as_ref());
+    play_box.update_pos_vis(
+        &game_panel.play_region,
+        &mut commands,
+        game_lib.as_ref(),
+        MoveKind::Snap,
+    );
+}
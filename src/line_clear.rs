@@ -0,0 +1,419 @@
+use crate::board::*;
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::motion::*;
+use crate::rules::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::{prelude::*, sprite_render::AlphaMode2d};
+use rand::prelude::*;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineClearEffectKind {
+    Flash,
+    Fade,
+    Shrink,
+    Slide,
+    Particles,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LineClearConfig {
+    pub effect: LineClearEffectKind,
+    pub flash: FlashConfig,
+    pub fade: FadeConfig,
+    pub shrink: ShrinkConfig,
+    pub slide: SlideConfig,
+    pub particles: ParticlesConfig,
+}
+
+impl LineClearConfig {
+    /// Seconds the selected effect plays, which is how long the game shows full rows
+    /// before removing them.
+    pub fn duration(&self) -> f32 {
+        match self.effect {
+            LineClearEffectKind::Flash => self.flash.duration,
+            LineClearEffectKind::Fade => self.fade.duration,
+            LineClearEffectKind::Shrink => self.shrink.duration,
+            LineClearEffectKind::Slide => self.slide.duration,
+            LineClearEffectKind::Particles => self.particles.duration,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FlashConfig {
+    pub duration: f32,
+    pub interval: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FadeConfig {
+    pub duration: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShrinkConfig {
+    pub duration: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SlideConfig {
+    pub duration: f32,
+    pub distance: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ParticlesConfig {
+    pub duration: f32,
+    pub count: u32,
+    pub size: f32,
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClearedBox {
+    pub entity: Entity,
+    pub pos: BoxPos,
+    pub translation: Vec2,
+    pub material: Handle<ColorMaterial>,
+}
+
+pub struct EffectContext<'a, 'w, 's> {
+    pub commands: &'a mut Commands<'w, 's>,
+    pub materials: &'a mut Assets<ColorMaterial>,
+    pub game_lib: &'a GameLib,
+}
+
+/// An animation played on the boxes of full rows before they are removed from the
+/// panel. `update` is called every frame with the progress of the effect in `0..=1`.
+pub trait LineClearEffect: Send + Sync {
+    fn duration(&self) -> f32;
+
+    fn start(&mut self, _boxes: &[ClearedBox], _ctx: &mut EffectContext) {}
+
+    fn update(&mut self, progress: f32, boxes: &[ClearedBox], ctx: &mut EffectContext);
+
+    fn finish(&mut self, _boxes: &[ClearedBox], _ctx: &mut EffectContext) {}
+}
+
+pub fn create_line_clear_effect(config: &LineClearConfig) -> Box<dyn LineClearEffect> {
+    match config.effect {
+        LineClearEffectKind::Flash => Box::new(FlashEffect(config.flash.clone())),
+        LineClearEffectKind::Fade => Box::new(FadeEffect {
+            config: config.fade.clone(),
+            materials: Vec::new(),
+        }),
+        LineClearEffectKind::Shrink => Box::new(ShrinkEffect(config.shrink.clone())),
+        LineClearEffectKind::Slide => Box::new(SlideEffect(config.slide.clone())),
+        LineClearEffectKind::Particles => Box::new(ParticlesEffect {
+            config: config.particles.clone(),
+            materials: Vec::new(),
+            particles: Vec::new(),
+        }),
+    }
+}
+
+pub struct FlashEffect(FlashConfig);
+
+impl LineClearEffect for FlashEffect {
+    fn duration(&self) -> f32 {
+        self.0.duration
+    }
+
+    fn update(&mut self, progress: f32, boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        let flip_count = (progress * self.0.duration / self.0.interval) as u32;
+        let vis = if flip_count.is_multiple_of(2) {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+
+        for b in boxes.iter() {
+            ctx.commands
+                .entity(b.entity)
+                .entry::<Visibility>()
+                .and_modify(move |mut v| {
+                    *v.as_mut() = vis;
+                });
+        }
+    }
+}
+
+pub struct FadeEffect {
+    config: FadeConfig,
+    materials: Vec<Handle<ColorMaterial>>,
+}
+
+impl LineClearEffect for FadeEffect {
+    fn duration(&self) -> f32 {
+        self.config.duration
+    }
+
+    fn start(&mut self, boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        // Boxes of the same type share a material, so each fading box gets its own copy.
+        self.materials.clear();
+        for b in boxes.iter() {
+            let handle = blend_material(&b.material, ctx.materials);
+            ctx.commands
+                .entity(b.entity)
+                .insert(MeshMaterial2d(handle.clone()));
+            self.materials.push(handle);
+        }
+    }
+
+    fn update(&mut self, progress: f32, _boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        set_alpha(&self.materials, 1.0 - progress, ctx.materials);
+    }
+
+    fn finish(&mut self, _boxes: &[ClearedBox], _ctx: &mut EffectContext) {
+        self.materials.clear();
+    }
+}
+
+pub struct ShrinkEffect(ShrinkConfig);
+
+impl LineClearEffect for ShrinkEffect {
+    fn duration(&self) -> f32 {
+        self.0.duration
+    }
+
+    fn update(&mut self, progress: f32, boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        let scale = Vec3::new(1.0 - progress, 1.0 - progress, 1.0);
+        for b in boxes.iter() {
+            ctx.commands
+                .entity(b.entity)
+                .entry::<Transform>()
+                .and_modify(move |mut t| {
+                    t.scale = scale;
+                });
+        }
+    }
+}
+
+pub struct SlideEffect(SlideConfig);
+
+impl LineClearEffect for SlideEffect {
+    fn duration(&self) -> f32 {
+        self.0.duration
+    }
+
+    fn update(&mut self, progress: f32, boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        let distance = self.0.distance * ctx.game_lib.scale * progress * progress;
+        for b in boxes.iter() {
+            // Alternate the direction so that neighbouring rows slide apart.
+            let dir = if b.pos.row % 2 == 0 { 1.0 } else { -1.0 };
+            let x = b.translation.x + dir * distance;
+            ctx.commands
+                .entity(b.entity)
+                .entry::<Transform>()
+                .and_modify(move |mut t| {
+                    t.translation.x = x;
+                });
+        }
+    }
+}
+
+pub struct ParticlesEffect {
+    config: ParticlesConfig,
+    materials: Vec<Handle<ColorMaterial>>,
+    particles: Vec<(Entity, Vec2, Vec2)>,
+}
+
+impl LineClearEffect for ParticlesEffect {
+    fn duration(&self) -> f32 {
+        self.config.duration
+    }
+
+    fn start(&mut self, boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        let mut rng = rand::rng();
+        let game_lib = ctx.game_lib;
+        let scale = game_lib.scale;
+        let box_config = &game_lib.config.box_config;
+        let particle_scale = Vec3::splat(self.config.size / box_config.size);
+        let z = box_config.z + 1.0;
+
+        self.materials.clear();
+        self.particles.clear();
+        for b in boxes.iter() {
+            let material = blend_material(&b.material, ctx.materials);
+            ctx.commands.entity(b.entity).insert(Visibility::Hidden);
+
+            for _ in 0..self.config.count {
+                let angle = rng.random_range(0.0..std::f32::consts::TAU);
+                let speed = self.config.speed * scale * rng.random_range(0.5..1.0);
+                let e = ctx.commands.spawn((
+                    Mesh2d(game_lib.box_mesh.clone()),
+                    MeshMaterial2d(material.clone()),
+                    Transform::from_xyz(b.translation.x, b.translation.y, z)
+                        .with_scale(particle_scale),
+                ));
+                self.particles
+                    .push((e.id(), b.translation, Vec2::from_angle(angle) * speed));
+            }
+
+            self.materials.push(material);
+        }
+    }
+
+    fn update(&mut self, progress: f32, _boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        let elapsed = progress * self.config.duration;
+        for (e, origin, velocity) in self.particles.iter() {
+            let pos = *origin + *velocity * elapsed;
+            ctx.commands
+                .entity(*e)
+                .entry::<Transform>()
+                .and_modify(move |mut t| {
+                    t.translation.x = pos.x;
+                    t.translation.y = pos.y;
+                });
+        }
+
+        set_alpha(&self.materials, 1.0 - progress, ctx.materials);
+    }
+
+    fn finish(&mut self, _boxes: &[ClearedBox], ctx: &mut EffectContext) {
+        for (e, _, _) in self.particles.drain(..) {
+            ctx.commands.entity(e).despawn();
+        }
+        self.materials.clear();
+    }
+}
+
+fn blend_material(
+    source: &Handle<ColorMaterial>,
+    materials: &mut Assets<ColorMaterial>,
+) -> Handle<ColorMaterial> {
+    let mut material = materials.get(source).cloned().unwrap_or_default();
+    material.alpha_mode = AlphaMode2d::Blend;
+    materials.add(material)
+}
+
+fn set_alpha(handles: &[Handle<ColorMaterial>], alpha: f32, materials: &mut Assets<ColorMaterial>) {
+    for handle in handles.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color.set_alpha(alpha);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct LineClearer {
+    effect: Box<dyn LineClearEffect>,
+    boxes: Vec<ClearedBox>,
+    elapsed: f32,
+}
+
+impl LineClearer {
+    pub fn new(config: &LineClearConfig) -> Self {
+        Self {
+            effect: create_line_clear_effect(config),
+            boxes: Vec::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn start(&mut self, boxes: Vec<ClearedBox>, ctx: &mut EffectContext) {
+        self.boxes = boxes;
+        self.elapsed = 0.0;
+        self.effect.start(&self.boxes, ctx);
+    }
+
+    /// Advances the effect. It stays at its last frame until [`LineClearer::finish`].
+    pub fn update(&mut self, time: &Time, ctx: &mut EffectContext) {
+        let duration = self.effect.duration();
+        self.elapsed += time.delta_secs();
+
+        let progress = if duration > 0.0 {
+            (self.elapsed / duration).min(1.0)
+        } else {
+            1.0
+        };
+        self.effect.update(progress, &self.boxes, ctx);
+    }
+
+    /// Ends the effect once the game has removed the full rows.
+    pub fn finish(&mut self, ctx: &mut EffectContext) {
+        self.effect.finish(&self.boxes, ctx);
+        self.boxes.clear();
+    }
+}
+
+pub fn start_line_clear(
+    mut commands: Commands,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    game_panel: Res<GamePanel>,
+    mut line_clearer: ResMut<LineClearer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    box_materials: Query<&MeshMaterial2d<ColorMaterial>>,
+) {
+    let boxes = cleared_boxes(
+        game_panel.as_ref(),
+        &game.board,
+        &box_materials,
+        &mut commands,
+        game_lib.as_ref(),
+    );
+    let mut ctx = EffectContext {
+        commands: &mut commands,
+        materials: materials.as_mut(),
+        game_lib: game_lib.as_ref(),
+    };
+    line_clearer.start(boxes, &mut ctx);
+}
+
+/// The blocks of the full rows of `board`, settled at their place on the panel.
+pub fn cleared_boxes(
+    game_panel: &GamePanel,
+    board: &Board,
+    box_materials: &Query<&MeshMaterial2d<ColorMaterial>>,
+    commands: &mut Commands,
+    game_lib: &GameLib,
+) -> Vec<ClearedBox> {
+    let mut boxes: Vec<ClearedBox> = Vec::new();
+    for (entity, pos) in game_panel.full_row_boxes(board) {
+        let Ok(material) = box_materials.get(entity) else {
+            continue;
+        };
+        let translation = get_box_pos(
+            &game_panel.play_region.box_origin,
+            pos.row,
+            pos.col,
+            game_lib.box_span,
+        );
+        // Settle any tween still running from the last move of the locked piece.
+        move_box(commands, entity, translation, translation, MoveKind::Snap);
+        boxes.push(ClearedBox {
+            entity,
+            pos,
+            translation,
+            material: material.0.clone(),
+        });
+    }
+    boxes
+}
+
+/// Plays the effect while the game shows the full rows, and ends it in the tick the
+/// rows are removed, before their blocks are despawned.
+pub fn animate_line_clear(
+    mut commands: Commands,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    mut line_clearer: ResMut<LineClearer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    time: Res<Time>,
+) {
+    let mut ctx = EffectContext {
+        commands: &mut commands,
+        materials: materials.as_mut(),
+        game_lib: game_lib.as_ref(),
+    };
+
+    line_clearer.update(time.as_ref(), &mut ctx);
+    if game.phase() != Phase::Clearing {
+        line_clearer.finish(&mut ctx);
+    }
+}
@@ -1,32 +1,260 @@
+mod accessibility;
+mod audio;
+mod bench;
+mod board;
+mod bot;
+mod env;
 mod game_lib;
 mod game_panel;
+mod headless;
+mod high_score;
+mod hint;
+mod input;
+mod layout;
+mod line_clear;
+mod messages;
+mod motion;
+mod music;
 mod my_error;
 mod play_box;
+mod pointer;
 mod preview;
+mod replay;
+mod rules;
+mod save;
+mod score;
+mod script;
+mod simulation;
 mod systems;
+mod theme;
+#[cfg(unix)]
+mod tui;
+mod tune;
 mod utils;
+mod versus;
 
+use crate::accessibility::*;
+use crate::audio::*;
+use crate::bench::*;
+use crate::bot::*;
+use crate::env::*;
+use crate::game_panel::*;
+use crate::headless::*;
+use crate::high_score::*;
+use crate::hint::*;
+use crate::input::*;
+use crate::layout::*;
+use crate::line_clear::*;
+use crate::messages::*;
+use crate::motion::*;
+use crate::music::*;
+use crate::pointer::*;
+use crate::replay::*;
+use crate::save::*;
+use crate::score::*;
+use crate::script::*;
 use crate::systems::*;
+use crate::theme::*;
+#[cfg(unix)]
+use crate::tui::*;
+use crate::tune::*;
 use crate::utils::*;
-use bevy::{log::LogPlugin, prelude::*};
+use crate::versus::*;
+use bevy::{
+    app::ScheduleRunnerPlugin, input::InputSystems, log::LogPlugin, prelude::*,
+    state::app::StatesPlugin,
+};
 use clap::Parser;
+use std::time::Duration;
 
 fn main() {
     let args = Args::parse();
 
     let _guard = setup_log(&args.log_path);
+    if let Some(command) = &args.command {
+        let (name, result) = match command {
+            ToolCommand::Bench(bench) => ("Benchmark", run_bench(&args, bench)),
+            ToolCommand::Tune(tune) => ("Tuning", run_tune(&args, tune)),
+        };
+        if let Err(err) = result {
+            error!("{} failed: {}", name, err);
+            eprintln!("{} failed: {}", name, err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    App::new()
-        .add_plugins(DefaultPlugins.build().disable::<LogPlugin>())
-        .insert_resource(args)
+    if args.env {
+        if let Err(err) = run_env(&args) {
+            error!("Environment failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // The terminal interface talks to the terminal through termios.
+    #[cfg(not(unix))]
+    if args.tui {
+        error!("The terminal interface is only available on Unix");
+        eprintln!("The terminal interface is only available on Unix");
+        std::process::exit(1);
+    }
+
+    let (headless, tui, versus) = (args.headless, args.tui, args.versus);
+
+    let mut app = App::new();
+    if headless || tui {
+        // Gameplay only needs the asset collections to exist; nothing is ever rendered.
+        #[cfg(unix)]
+        let wait = if headless {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(1.0 / TUI_FRAME_RATE)
+        };
+        #[cfg(not(unix))]
+        let wait = Duration::ZERO;
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(wait)),
+            StatesPlugin,
+        ))
+        .init_resource::<Assets<Mesh>>()
+        .init_resource::<Assets<ColorMaterial>>()
+        .add_message::<GameplayMessage>();
+    } else {
+        app.add_plugins(DefaultPlugins.build().disable::<LogPlugin>())
+            .add_plugins((GameAudioPlugin, MusicPlugin));
+    }
+
+    app.insert_resource(args)
         .init_state::<AppState>()
-        .add_systems(Startup, setup_game)
-        .add_systems(Update, reset_play_box.run_if(in_state(AppState::InitBox)))
+        .init_state::<PauseState>();
+
+    if versus {
+        app.add_plugins(VersusPlugin).run();
+        return;
+    }
+
+    app.add_systems(Startup, setup_game)
+        .init_resource::<GameClock>()
+        .init_resource::<TickEvents>()
+        .init_resource::<PendingActions>()
+        .init_resource::<TickActions>()
+        .add_message::<RestartGame>()
+        .add_systems(
+            FixedUpdate,
+            (
+                run_bot.run_if(resource_exists::<Bot>.and(in_state(AppState::Playing))),
+                begin_tick.run_if(not(in_state(AppState::Stopped))),
+                (
+                    play_tick.run_if(not(in_state(AppState::Stopped))),
+                    sync_pieces,
+                    animate_line_clear.run_if(in_state(AppState::ClearingRows)),
+                    sync_board,
+                    check_board.run_if(resource_exists::<ScriptChecks>),
+                    animate_boxes,
+                    track_play_time.run_if(not(in_state(AppState::Stopped))),
+                )
+                    .chain(),
+                apply_tick_transitions,
+            )
+                .chain()
+                .run_if(
+                    resource_exists::<GamePanel>
+                        .and(in_state(PauseState::Running))
+                        .and(tick_allowed),
+                ),
+        )
+        .add_systems(OnEnter(AppState::ClearingRows), start_line_clear)
+        .add_systems(
+            OnEnter(AppState::Stopped),
+            (announce_game_over, save_replay),
+        );
+
+    if headless {
+        app.add_systems(OnEnter(AppState::Stopped), report_result);
+    } else if tui {
+        #[cfg(unix)]
+        app.add_plugins(TuiPlugin);
+    } else {
+        add_frontend_systems(&mut app);
+    }
+
+    app.run();
+}
+
+/// Systems reading the keyboard, mouse and window, and drawing everything besides the
+/// blocks, which are not used when running headless.
+fn add_frontend_systems(app: &mut App) {
+    app.add_message::<SelectTheme>()
+        .init_resource::<HighScoreScreen>()
+        .init_resource::<PointerTracker>()
+        .add_systems(Startup, spawn_high_score_panel)
+        .add_systems(
+            PreUpdate,
+            (
+                read_input.run_if(resource_exists::<InputBindings>),
+                read_pointer.run_if(resource_exists::<GamePanel>),
+                queue_actions.run_if(
+                    resource_exists::<ActionState>
+                        .and(not(resource_exists::<ReplayPlayer>))
+                        .and(in_state(PauseState::Running)),
+                ),
+            )
+                .chain()
+                .after(InputSystems),
+        )
         .add_systems(
             Update,
-            (process_input, drop_down_play_box).run_if(in_state(AppState::Playing)),
+            (
+                control_playback.run_if(resource_exists::<ReplayPlayer>),
+                restart_game.run_if(resource_exists::<GamePanel>),
+            )
+                .chain(),
         )
-        .add_systems(Update, fast_move_down.run_if(in_state(AppState::FastDown)))
-        .add_systems(Update, flash_full_rows.run_if(in_state(AppState::Flashing)))
-        .run();
+        .add_systems(
+            OnEnter(AppState::Stopped),
+            show_game_over_scores.run_if(not(resource_exists::<ReplayPlayer>)),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_high_scores,
+                enter_high_score_name.run_if(in_state(AppState::Stopped)),
+                update_high_score_panel.run_if(
+                    resource_exists::<HighScores>.and(
+                        resource_changed::<HighScoreScreen>.or(resource_changed::<HighScores>),
+                    ),
+                ),
+            )
+                .chain(),
+        )
+        .add_systems(Update, toggle_pause.run_if(resource_exists::<ActionState>))
+        .add_systems(
+            Update,
+            save_game.run_if(in_state(AppState::Playing).and(not(resource_exists::<ReplayPlayer>))),
+        )
+        .add_systems(Update, update_layout.run_if(resource_exists::<GamePanel>))
+        .add_systems(
+            Update,
+            toggle_smooth_motion.run_if(resource_exists::<MotionConfig>),
+        )
+        .add_systems(
+            Update,
+            (toggle_hint, update_hint, draw_hint)
+                .chain()
+                .run_if(resource_exists::<Hint>),
+        )
+        .add_systems(
+            Update,
+            (
+                switch_theme.run_if(resource_exists::<ThemeManager>),
+                switch_accessibility.run_if(resource_exists::<AccessibilitySettings>),
+                apply_theme.run_if(
+                    resource_exists_and_changed::<ThemeManager>
+                        .or(resource_exists_and_changed::<AccessibilitySettings>),
+                ),
+                update_piece_glyphs.run_if(resource_exists::<AccessibilitySettings>),
+            )
+                .chain(),
+        );
 }
@@ -0,0 +1,12 @@
+use bevy::prelude::*;
+
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameplayMessage {
+    Move,
+    Rotate,
+    Lock,
+    LineClear(usize),
+    Hold,
+    LevelUp(u32),
+    GameOver,
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+#[derive(Resource, Debug, Deserialize, Clone)]
+pub struct MotionConfig {
+    pub smooth: bool,
+    pub move_duration: f32,
+    pub rotate_duration: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CollapseConfig {
+    pub instant: bool,
+    /// Falling speed of the rows above cleared lines, in rows per second.
+    pub speed: f32,
+    pub easing: Easing,
+}
+
+impl CollapseConfig {
+    /// Number of gameplay ticks the rows above cleared lines take to fall `rows` rows.
+    /// It only depends on the config and the tick length, so the game waits the same
+    /// whatever the window size or the smooth motion setting.
+    pub fn ticks(&self, rows: usize, tick_secs: f32) -> u32 {
+        if self.instant || self.speed <= 0.0 || tick_secs <= 0.0 {
+            return 0;
+        }
+        (rows as f32 / self.speed / tick_secs).ceil() as u32
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MoveKind {
+    Snap,
+    Slide,
+    /// Rotation of a whole piece by the given angle (radians) around its pivot.
+    Rotate(f32),
+    /// Fall at the given speed (world units per second), independent of the smooth
+    /// motion setting.
+    Fall {
+        speed: f32,
+        easing: Easing,
+    },
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct BoxTween {
+    from: Vec2,
+    to: Vec2,
+    pivot: Vec2,
+    angle: f32,
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+impl BoxTween {
+    pub fn new(from: Vec2, to: Vec2, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            pivot: Vec2::ZERO,
+            angle: 0.0,
+            elapsed: 0.0,
+            duration,
+            easing: Easing::EaseOut,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_rotation(mut self, pivot: Vec2, angle: f32) -> Self {
+        self.pivot = pivot;
+        self.angle = angle;
+        self
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn update(&mut self, delta: f32) -> Vec2 {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        let eased = self.easing.apply(t);
+
+        if self.angle == 0.0 {
+            return self.from.lerp(self.to, eased);
+        }
+
+        // Orbit around the pivot and blend in the remaining offset, since a rotated
+        // bitmap does not necessarily land exactly where the orbit ends.
+        let offset = self.from - self.pivot;
+        let arc = self.pivot + Vec2::from_angle(self.angle * eased).rotate(offset);
+        let arc_end = self.pivot + Vec2::from_angle(self.angle).rotate(offset);
+        arc + (self.to - arc_end) * eased
+    }
+}
+
+impl MotionConfig {
+    pub fn duration(&self, kind: MoveKind, distance: f32) -> f32 {
+        match kind {
+            MoveKind::Snap => 0.0,
+            MoveKind::Fall { speed, .. } if speed > 0.0 => distance / speed,
+            MoveKind::Fall { .. } => 0.0,
+            _ if !self.smooth => 0.0,
+            MoveKind::Slide => self.move_duration,
+            MoveKind::Rotate(_) => self.rotate_duration,
+        }
+    }
+}
+
+/// Moves a box entity to `target`, either instantly or by attaching a [`BoxTween`]
+/// depending on the current [`MotionConfig`].
+pub fn move_box(
+    commands: &mut Commands,
+    entity: Entity,
+    target: Vec2,
+    pivot: Vec2,
+    kind: MoveKind,
+) {
+    commands
+        .entity(entity)
+        .queue(move |mut entity: EntityWorldMut| {
+            let Some(from) = entity.get::<Transform>().map(|t| t.translation.truncate()) else {
+                return;
+            };
+            let duration = entity
+                .world()
+                .get_resource::<MotionConfig>()
+                .map(|config| config.duration(kind, from.distance(target)))
+                .unwrap_or(0.0);
+            let Some(mut transform) = entity.get_mut::<Transform>() else {
+                return;
+            };
+
+            if duration <= 0.0 {
+                transform.translation.x = target.x;
+                transform.translation.y = target.y;
+                entity.remove::<BoxTween>();
+                return;
+            }
+
+            let mut tween = BoxTween::new(from, target, duration);
+            match kind {
+                MoveKind::Rotate(angle) => tween = tween.with_rotation(pivot, angle),
+                MoveKind::Fall { easing, .. } => tween = tween.with_easing(easing),
+                _ => {}
+            }
+            entity.insert(tween);
+        });
+}
+
+pub fn toggle_smooth_motion(keys: Res<ButtonInput<KeyCode>>, mut motion: ResMut<MotionConfig>) {
+    if keys.just_pressed(KeyCode::F3) {
+        motion.smooth = !motion.smooth;
+        info!("Smooth motion {}", if motion.smooth { "on" } else { "off" });
+    }
+}
+
+pub fn animate_boxes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tweens: Query<(Entity, &mut BoxTween, &mut Transform)>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut tween, mut transform) in tweens.iter_mut() {
+        let pos = tween.update(delta);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+
+        if tween.is_finished() {
+            commands.entity(entity).remove::<BoxTween>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapse_ticks_depend_only_on_rows_and_tick_length() {
+        let mut config = CollapseConfig {
+            instant: false,
+            speed: 8.0,
+            easing: Easing::Linear,
+        };
+        assert_eq!(config.ticks(0, 0.0625), 0);
+        assert_eq!(config.ticks(1, 0.0625), 2);
+        assert_eq!(config.ticks(4, 0.0625), 8);
+
+        config.instant = true;
+        assert_eq!(config.ticks(4, 0.0625), 0);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::systems::*;
+use bevy::{audio::Volume, prelude::*};
+use serde::Deserialize;
+
+#[derive(Resource, Debug, Deserialize, Clone)]
+pub struct MusicConfig {
+    /// Track paths relative to the asset folder.
+    pub tracks: Vec<String>,
+    pub mode: PlaylistMode,
+    pub volume: f32,
+    pub fade_duration: f32,
+    /// Changes applied once the level reaches `level`; the last matching entry wins.
+    pub level_changes: Vec<LevelMusic>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistMode {
+    /// Repeat the current track until the level switches it.
+    Loop,
+    /// Play the tracks one after another, wrapping around at the end.
+    Advance,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LevelMusic {
+    pub level: u32,
+    pub speed: f32,
+    pub track: Option<usize>,
+}
+
+impl MusicConfig {
+    pub fn level_music(&self, level: u32) -> Option<&LevelMusic> {
+        self.level_changes.iter().rev().find(|m| m.level <= level)
+    }
+}
+
+#[derive(Component)]
+pub struct MusicTrack;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FadeEnd {
+    Nothing,
+    Pause,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MusicFade {
+    from: f32,
+    to: f32,
+    elapsed: f32,
+    duration: f32,
+    end: FadeEnd,
+}
+
+#[derive(Resource, Debug)]
+pub struct MusicPlayer {
+    track: usize,
+    entity: Option<Entity>,
+    speed: f32,
+    /// Fade factor in `0..=1` applied on top of the configured volume.
+    level: f32,
+    fade: Option<MusicFade>,
+}
+
+impl Default for MusicPlayer {
+    fn default() -> Self {
+        Self {
+            track: 0,
+            entity: None,
+            speed: 1.0,
+            level: 1.0,
+            fade: None,
+        }
+    }
+}
+
+impl MusicPlayer {
+    pub fn play(
+        &mut self,
+        track: usize,
+        config: &MusicConfig,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+    ) {
+        if let Some(e) = self.entity.take() {
+            commands.entity(e).despawn();
+        }
+
+        let Some(path) = config.tracks.get(track) else {
+            return;
+        };
+
+        let settings = match config.mode {
+            PlaylistMode::Loop => PlaybackSettings::LOOP,
+            PlaylistMode::Advance => PlaybackSettings::DESPAWN,
+        }
+        .with_volume(Volume::Linear(config.volume * self.level))
+        .with_speed(self.speed);
+
+        let e = commands.spawn((
+            AudioPlayer::new(asset_server.load(path)),
+            settings,
+            MusicTrack,
+        ));
+        self.entity = Some(e.id());
+        self.track = track;
+
+        info!("Playing music track {} ({})", track, path);
+    }
+
+    fn fade_to(&mut self, to: f32, duration: f32, end: FadeEnd) {
+        self.fade = Some(MusicFade {
+            from: self.level,
+            to,
+            elapsed: 0.0,
+            duration,
+            end,
+        });
+    }
+}
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicPlayer>()
+            .add_systems(
+                Update,
+                (
+                    start_music.run_if(resource_added::<MusicConfig>),
+                    advance_playlist.run_if(resource_exists::<MusicConfig>),
+                    update_level_music
+                        .run_if(resource_exists::<MusicConfig>.and(resource_changed::<Game>)),
+                    fade_music.run_if(resource_exists::<MusicConfig>),
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(PauseState::Paused), fade_out_on_pause)
+            .add_systems(OnExit(PauseState::Paused), fade_in_on_resume)
+            .add_systems(OnEnter(AppState::Stopped), fade_out_on_game_over)
+            .add_systems(OnExit(AppState::Stopped), restart_music);
+    }
+}
+
+pub fn start_music(
+    mut commands: Commands,
+    config: Res<MusicConfig>,
+    asset_server: Res<AssetServer>,
+    mut player: ResMut<MusicPlayer>,
+) {
+    player.play(0, config.as_ref(), &mut commands, asset_server.as_ref());
+}
+
+pub fn advance_playlist(
+    mut commands: Commands,
+    config: Res<MusicConfig>,
+    asset_server: Res<AssetServer>,
+    mut player: ResMut<MusicPlayer>,
+    tracks: Query<(), With<MusicTrack>>,
+) {
+    let Some(e) = player.entity else {
+        return;
+    };
+
+    // In advance mode the track entity despawns itself once it has been played.
+    if tracks.contains(e) || config.tracks.is_empty() {
+        return;
+    }
+
+    player.entity = None;
+    let next = (player.track + 1) % config.tracks.len();
+    player.play(next, config.as_ref(), &mut commands, asset_server.as_ref());
+}
+
+pub fn update_level_music(
+    mut commands: Commands,
+    config: Res<MusicConfig>,
+    asset_server: Res<AssetServer>,
+    game: Res<Game>,
+    mut player: ResMut<MusicPlayer>,
+    sinks: Query<&AudioSink, With<MusicTrack>>,
+) {
+    let (speed, track) = match config.level_music(game.scoreboard.level) {
+        Some(m) => (m.speed, m.track),
+        None => (1.0, None),
+    };
+
+    if speed != player.speed {
+        player.speed = speed;
+        for sink in sinks.iter() {
+            sink.set_speed(speed);
+        }
+    }
+
+    if let Some(track) = track
+        && track != player.track
+        && player.entity.is_some()
+    {
+        player.play(track, config.as_ref(), &mut commands, asset_server.as_ref());
+    }
+}
+
+pub fn fade_music(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<MusicConfig>,
+    mut player: ResMut<MusicPlayer>,
+    mut sinks: Query<&mut AudioSink, With<MusicTrack>>,
+) {
+    let Some(mut fade) = player.fade else {
+        return;
+    };
+
+    fade.elapsed += time.delta_secs();
+    let t = if fade.duration > 0.0 {
+        (fade.elapsed / fade.duration).min(1.0)
+    } else {
+        1.0
+    };
+    player.level = fade.from + (fade.to - fade.from) * t;
+
+    for mut sink in sinks.iter_mut() {
+        sink.set_volume(Volume::Linear(config.volume * player.level));
+        if t >= 1.0 && fade.end == FadeEnd::Pause {
+            sink.pause();
+        }
+    }
+
+    if t < 1.0 {
+        player.fade = Some(fade);
+        return;
+    }
+
+    player.fade = None;
+    if fade.end == FadeEnd::Stop
+        && let Some(e) = player.entity.take()
+    {
+        commands.entity(e).despawn();
+    }
+}
+
+pub fn fade_out_on_pause(config: Option<Res<MusicConfig>>, mut player: ResMut<MusicPlayer>) {
+    if let Some(config) = config {
+        player.fade_to(0.0, config.fade_duration, FadeEnd::Pause);
+    }
+}
+
+pub fn fade_in_on_resume(
+    config: Option<Res<MusicConfig>>,
+    mut player: ResMut<MusicPlayer>,
+    sinks: Query<&AudioSink, With<MusicTrack>>,
+) {
+    let Some(config) = config else {
+        return;
+    };
+
+    for sink in sinks.iter() {
+        sink.play();
+    }
+    player.fade_to(1.0, config.fade_duration, FadeEnd::Nothing);
+}
+
+pub fn fade_out_on_game_over(config: Option<Res<MusicConfig>>, mut player: ResMut<MusicPlayer>) {
+    if let Some(config) = config {
+        player.fade_to(0.0, config.fade_duration, FadeEnd::Stop);
+    }
+}
+
+/// Starts the playlist over at full volume and speed when a new game follows a game
+/// over, which stopped the music.
+pub fn restart_music(
+    mut commands: Commands,
+    config: Option<Res<MusicConfig>>,
+    asset_server: Res<AssetServer>,
+    mut player: ResMut<MusicPlayer>,
+) {
+    let Some(config) = config else {
+        return;
+    };
+
+    player.fade = None;
+    player.level = 1.0;
+    player.speed = 1.0;
+    player.play(0, config.as_ref(), &mut commands, asset_server.as_ref());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level_change(level: u32, speed: f32, track: Option<usize>) -> LevelMusic {
+        LevelMusic {
+            level,
+            speed,
+            track,
+        }
+    }
+
+    #[test]
+    fn level_music_uses_the_last_reached_threshold() {
+        let config = MusicConfig {
+            tracks: vec!["a.ogg".to_string(), "b.ogg".to_string()],
+            mode: PlaylistMode::Loop,
+            volume: 1.0,
+            fade_duration: 0.0,
+            level_changes: vec![level_change(3, 1.1, None), level_change(6, 1.2, Some(1))],
+        };
+
+        let speed = |level| config.level_music(level).map(|m| m.speed);
+        assert_eq!(speed(0), None);
+        assert_eq!(speed(2), None);
+        assert_eq!(speed(3), Some(1.1));
+        assert_eq!(speed(5), Some(1.1));
+        assert_eq!(speed(6), Some(1.2));
+        assert_eq!(config.level_music(20).and_then(|m| m.track), Some(1));
+    }
+}
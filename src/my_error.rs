@@ -8,4 +8,31 @@ pub enum MyError {
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("Unknown theme: {0}")]
+    UnknownTheme(String),
+
+    #[error("No key bound to action {0}")]
+    MissingBinding(String),
+
+    #[error("Invalid key name: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid gamepad control name: {0}")]
+    InvalidGamepadControl(String),
+
+    #[error("Key {0} is bound to both {1} and {2}")]
+    ConflictingBinding(String, String, String),
+
+    #[error("Corrupted high score file {0}: {1}")]
+    CorruptedHighScores(String, String),
+
+    #[error("Invalid saved game: {0}")]
+    InvalidSave(String),
+
+    #[error("Invalid input script: {0}")]
+    InvalidScript(String),
+
+    #[error("Tuning checkpoint does not match this run: {0}")]
+    MismatchedCheckpoint(String),
 }
@@ -1,38 +1,97 @@
+use crate::accessibility::*;
+use crate::board::*;
 use crate::game_lib::*;
-use crate::game_panel::*;
+use crate::motion::*;
 use crate::utils::*;
 use bevy::prelude::*;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Resource, Debug, Clone)]
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BoxIndex {
     pub type_index: usize,
     pub rotate_index: usize,
 }
 
 impl BoxIndex {
-    pub fn rotate(&mut self) {
-        self.rotate_index = (self.rotate_index + 1) % PLAY_BOX_ROTATE_COUNT;
+    pub fn rotate(&mut self, rotation: Rotation) {
+        self.rotate_index = (self.rotate_index + rotation.steps()) % PLAY_BOX_ROTATE_COUNT;
     }
 }
 
-#[derive(Resource)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Cw,
+    Ccw,
+    Half,
+}
+
+impl Rotation {
+    /// Number of clockwise rotation steps, each one advancing the rotate index by one.
+    pub fn steps(&self) -> usize {
+        match self {
+            Rotation::Cw => 1,
+            Rotation::Ccw => PLAY_BOX_ROTATE_COUNT - 1,
+            Rotation::Half => 2,
+        }
+    }
+
+    pub fn angle(&self) -> f32 {
+        match self {
+            Rotation::Cw => -std::f32::consts::FRAC_PI_2,
+            Rotation::Ccw => std::f32::consts::FRAC_PI_2,
+            Rotation::Half => -std::f32::consts::PI,
+        }
+    }
+}
+
+/// Random piece generator. The sequence is fully determined by the seed, so its state
+/// can be saved as the seed plus the number of pieces drawn so far.
+#[derive(Debug, Clone)]
 pub struct IndexGen {
     type_count: usize,
     rotate_count: usize,
+    seed: u64,
+    draws: u64,
     rng: StdRng,
 }
 
 impl IndexGen {
     pub fn new(type_count: usize, rotate_count: usize) -> Self {
+        Self::with_seed(type_count, rotate_count, rand::rng().random())
+    }
+
+    pub fn with_seed(type_count: usize, rotate_count: usize, seed: u64) -> Self {
         IndexGen {
             type_count,
             rotate_count,
-            rng: StdRng::from_os_rng(),
+            seed,
+            draws: 0,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
+    /// Recreates a generator that has already produced `draws` pieces.
+    pub fn restore(type_count: usize, rotate_count: usize, seed: u64, draws: u64) -> Self {
+        let mut index_gen = Self::with_seed(type_count, rotate_count, seed);
+        for _ in 0..draws {
+            index_gen.rand_box();
+        }
+        index_gen
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
     pub fn rand_box(&mut self) -> BoxIndex {
+        self.draws += 1;
         let type_index = self.rng.random_range(0..self.type_count);
         let rotate_index = self.rng.random_range(0..self.rotate_count);
         BoxIndex {
@@ -42,18 +101,6 @@ impl IndexGen {
     }
 }
 
-#[derive(Resource, Component, Debug, Clone, Default)]
-pub struct BoxPos {
-    pub row: i32,
-    pub col: i32,
-}
-
-impl BoxPos {
-    pub fn new(row: i32, col: i32) -> Self {
-        Self { row, col }
-    }
-}
-
 #[derive(Resource, Debug)]
 pub struct PlayBoxRegion {
     pub box_origin: Vec2,
@@ -70,6 +117,16 @@ impl PlayBoxRegion {
         }
     }
 
+    /// Returns the cell of the region under the world position `pos`, if any.
+    pub fn cell_at(&self, pos: &Vec2, box_span: f32) -> Option<BoxPos> {
+        let (row, col) = get_box_row_col(&self.box_origin, pos, box_span);
+        if (0..self.row_count as i32).contains(&row) && (0..self.col_count as i32).contains(&col) {
+            Some(BoxPos::new(row, col))
+        } else {
+            None
+        }
+    }
+
     pub fn get_visibility(&self, row: i32, col: i32) -> Visibility {
         if (0..self.row_count as i32).contains(&row) && (0..self.col_count as i32).contains(&col) {
             Visibility::Visible
@@ -79,6 +136,31 @@ impl PlayBoxRegion {
     }
 }
 
+pub fn spawn_box(
+    type_index: usize,
+    pos: Vec2,
+    visibility: Visibility,
+    game_lib: &GameLib,
+    commands: &mut Commands,
+) -> Entity {
+    let mut e = commands.spawn((
+        Transform::from_xyz(pos.x, pos.y, game_lib.config.box_config.z),
+        visibility,
+    ));
+    if game_lib.headless {
+        return e.id();
+    }
+
+    e.insert((
+        Mesh2d(game_lib.box_mesh.clone()),
+        MeshMaterial2d(game_lib.box_colors[type_index].clone()),
+    ));
+    if let Some(glyph) = piece_glyph(type_index, game_lib) {
+        e.with_child(glyph);
+    }
+    e.id()
+}
+
 #[derive(Resource, Debug, Default)]
 pub struct PlayBox {
     pos: BoxPos,
@@ -116,11 +198,12 @@ impl PlayBox {
         }
 
         self.pos = new_pos;
-        self.update_pos_vis(region, commands, game_lib);
+        self.update_pos_vis(region, commands, game_lib, MoveKind::Slide);
     }
 
     pub fn rotate(
         &mut self,
+        rotation: Rotation,
         region: &PlayBoxRegion,
         game_lib: &GameLib,
         commands: &mut Commands,
@@ -129,18 +212,13 @@ impl PlayBox {
             return;
         }
 
-        self.index.as_mut().unwrap().rotate();
-        self.update_pos_vis(region, commands, game_lib);
-    }
-
-    #[inline]
-    pub fn pos(&self) -> &BoxPos {
-        &self.pos
-    }
-
-    #[inline]
-    pub fn index(&self) -> Option<&BoxIndex> {
-        self.index.as_ref()
+        self.index.as_mut().unwrap().rotate(rotation);
+        self.update_pos_vis(
+            region,
+            commands,
+            game_lib,
+            MoveKind::Rotate(rotation.angle()),
+        );
     }
 
     #[inline]
@@ -159,27 +237,28 @@ impl PlayBox {
         self.pos = new_pos;
         self.index = source.index.take();
         self.transfer_entities(source);
-        self.update_pos_vis(new_region, commands, game_lib);
+        self.update_pos_vis(new_region, commands, game_lib, MoveKind::Snap);
     }
 
-    pub fn put_in_panel(&mut self, game_lib: &GameLib, game_panel: &mut GamePanel) {
-        let Some(index) = &self.index else {
-            return;
+    /// Gives up the block entities, paired with the panel cell each one occupies.
+    pub fn take_entities(&mut self, game_lib: &GameLib) -> Vec<(BoxPos, Entity)> {
+        let Some(index) = self.index.take() else {
+            return Vec::new();
         };
-        let box_pos = game_lib.box_pos(index);
-        let mut it = box_pos.iter();
 
-        for e in self.entities.iter() {
-            if let Some(pos) = it.next() {
-                let row = self.pos.row + pos.row;
-                let col = self.pos.col + pos.col;
+        game_lib
+            .box_pos(&index)
+            .iter()
+            .map(|pos| BoxPos::new(self.pos.row + pos.row, self.pos.col + pos.col))
+            .zip(self.entities.drain(..))
+            .collect()
+    }
 
-                game_panel.put_in_entity(row, col, e.clone());
-            }
+    pub fn despawn(&mut self, commands: &mut Commands) {
+        for e in self.entities.drain(..) {
+            commands.entity(e).despawn();
         }
-
         self.index = None;
-        self.entities.clear();
     }
 
     fn add_components(
@@ -196,12 +275,9 @@ impl PlayBox {
             self.pos.col,
             game_lib.box_span,
         );
-        let color = &game_lib.box_colors[index.type_index];
         let box_span = game_lib.box_span;
-        let box_config = &config.box_config;
-        let bitmap = box_config.play_box_bitmap(index);
+        let bitmap = config.box_config.play_box_bitmap(index);
         let mut y = init_pos.y;
-        let z = box_config.z;
         let mut row = self.pos.row;
 
         self.entities.clear();
@@ -210,13 +286,14 @@ impl PlayBox {
             let mut col = self.pos.col;
             for c in 0..PLAY_BOX_BITMAP_SIZE {
                 if bitmap[r][c] != 0 {
-                    let e = commands.spawn((
-                        Mesh2d(game_lib.box_mesh.clone()),
-                        MeshMaterial2d(color.clone()),
-                        Transform::from_xyz(x, y, z),
+                    let e = spawn_box(
+                        index.type_index,
+                        Vec2::new(x, y),
                         region.get_visibility(row, col),
-                    ));
-                    self.entities.push(e.id());
+                        game_lib,
+                        commands,
+                    );
+                    self.entities.push(e);
                 }
                 x += box_span;
                 col += 1;
@@ -234,30 +311,41 @@ impl PlayBox {
         source.entities.clear();
     }
 
-    fn update_pos_vis(&self, region: &PlayBoxRegion, commands: &mut Commands, game_lib: &GameLib) {
+    pub fn update_pos_vis(
+        &self,
+        region: &PlayBoxRegion,
+        commands: &mut Commands,
+        game_lib: &GameLib,
+        kind: MoveKind,
+    ) {
         let Some(index) = &self.index else {
             return;
         };
-        let box_pos = game_lib.box_pos(index);
-        let mut it = box_pos.iter();
-
-        for e in self.entities.iter() {
-            if let Some(pos) = it.next() {
+        let targets: Vec<(i32, i32, Vec2)> = game_lib
+            .box_pos(index)
+            .iter()
+            .map(|pos| {
                 let row = self.pos.row + pos.row;
                 let col = self.pos.col + pos.col;
-                let p = get_box_pos(&region.box_origin, row, col, game_lib.box_span);
-                let v = region.get_visibility(row, col);
-                let mut entity = commands.entity(e.clone());
-
-                entity.entry::<Transform>().and_modify(move |mut t| {
-                    t.translation.x = p.x;
-                    t.translation.y = p.y;
-                });
-
-                entity.entry::<Visibility>().and_modify(move |mut vis| {
+                (
+                    row,
+                    col,
+                    get_box_pos(&region.box_origin, row, col, game_lib.box_span),
+                )
+            })
+            .collect();
+        let pivot = targets.iter().map(|(_, _, p)| *p).sum::<Vec2>() / targets.len().max(1) as f32;
+
+        for (e, (row, col, p)) in self.entities.iter().zip(targets) {
+            let v = region.get_visibility(row, col);
+
+            move_box(commands, *e, p, pivot, kind);
+            commands
+                .entity(*e)
+                .entry::<Visibility>()
+                .and_modify(move |mut vis| {
                     *vis.as_mut() = v;
                 });
-            }
         }
     }
 }
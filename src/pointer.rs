@@ -0,0 +1,160 @@
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::input::*;
+use bevy::{input::touch::Touches, prelude::*};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PointerConfig {
+    pub enabled: bool,
+    /// Minimum downward travel, in logical pixels, for a drag to count as a swipe.
+    pub swipe_distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerGesture {
+    Tap(Vec2),
+    SwipeDown,
+}
+
+impl PointerGesture {
+    /// Classifies a press released at `end` after starting at `start`, both in window
+    /// coordinates where y grows downwards.
+    pub fn classify(start: Vec2, end: Vec2, config: &PointerConfig) -> Self {
+        let travel = end - start;
+        if travel.y >= config.swipe_distance && travel.y > travel.x.abs() {
+            PointerGesture::SwipeDown
+        } else {
+            PointerGesture::Tap(start)
+        }
+    }
+}
+
+/// Presses in progress for the mouse and for the touch screen.
+#[derive(Resource, Debug, Default)]
+pub struct PointerTracker {
+    mouse_start: Option<Vec2>,
+    touch_start: Option<Vec2>,
+    /// Largest number of fingers down at once since the current touch started.
+    touch_count: usize,
+}
+
+pub fn read_pointer(
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    game_lib: Res<GameLib>,
+    game_panel: Res<GamePanel>,
+    mut pointer: ResMut<PointerTracker>,
+    mut actions: ResMut<ActionState>,
+) {
+    let config = &game_lib.config.pointer_config;
+    if !config.enabled {
+        return;
+    }
+
+    let mut gestures: Vec<PointerGesture> = Vec::new();
+    let cursor = window.cursor_position();
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        pointer.mouse_start = cursor;
+    }
+    if mouse_buttons.just_released(MouseButton::Left)
+        && let (Some(start), Some(end)) = (pointer.mouse_start.take(), cursor)
+    {
+        gestures.push(PointerGesture::classify(start, end, config));
+    }
+    if mouse_buttons.just_pressed(MouseButton::Right) {
+        actions.trigger(InputAction::RotateCw);
+    }
+
+    if let Some(touch) = touches.iter_just_pressed().next()
+        && pointer.touch_start.is_none()
+    {
+        pointer.touch_start = Some(touch.position());
+    }
+    pointer.touch_count = pointer.touch_count.max(touches.iter().count());
+
+    let released = touches.iter_just_released().last().map(|t| t.position());
+    if let Some(end) = released
+        && touches.iter().next().is_none()
+    {
+        if let Some(start) = pointer.touch_start.take() {
+            if pointer.touch_count >= 2 {
+                actions.trigger(InputAction::RotateCw);
+            } else {
+                gestures.push(PointerGesture::classify(start, end, config));
+            }
+        }
+        pointer.touch_count = 0;
+    }
+
+    let (camera, camera_transform) = *camera;
+    for gesture in gestures {
+        match gesture {
+            PointerGesture::SwipeDown => actions.trigger(InputAction::HardDrop),
+            PointerGesture::Tap(pos) => {
+                let Ok(world_pos) = camera.viewport_to_world_2d(camera_transform, pos) else {
+                    continue;
+                };
+                if let Some(cell) = game_panel
+                    .play_region
+                    .cell_at(&world_pos, game_lib.box_span)
+                {
+                    actions.set_target_col(cell.col);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: PointerConfig = PointerConfig {
+        enabled: true,
+        swipe_distance: 60.0,
+    };
+
+    fn classify(start: [f32; 2], end: [f32; 2]) -> PointerGesture {
+        PointerGesture::classify(Vec2::from(start), Vec2::from(end), &CONFIG)
+    }
+
+    #[test]
+    fn short_presses_are_taps_where_they_started() {
+        assert_eq!(
+            classify([100.0, 200.0], [102.0, 203.0]),
+            PointerGesture::Tap(Vec2::new(100.0, 200.0))
+        );
+        assert_eq!(
+            classify([100.0, 200.0], [100.0, 259.0]),
+            PointerGesture::Tap(Vec2::new(100.0, 200.0))
+        );
+    }
+
+    #[test]
+    fn long_downward_drags_are_swipes() {
+        assert_eq!(
+            classify([100.0, 200.0], [100.0, 260.0]),
+            PointerGesture::SwipeDown
+        );
+        assert_eq!(
+            classify([100.0, 200.0], [150.0, 320.0]),
+            PointerGesture::SwipeDown
+        );
+    }
+
+    #[test]
+    fn diagonal_and_upward_drags_are_taps() {
+        assert_eq!(
+            classify([100.0, 200.0], [190.0, 280.0]),
+            PointerGesture::Tap(Vec2::new(100.0, 200.0))
+        );
+        assert_eq!(
+            classify([100.0, 200.0], [100.0, 100.0]),
+            PointerGesture::Tap(Vec2::new(100.0, 200.0))
+        );
+    }
+}
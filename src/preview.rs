@@ -1,45 +1,102 @@
+use crate::board::*;
 use crate::game_lib::*;
+use crate::layout::*;
+use crate::motion::*;
 use crate::play_box::*;
+use crate::theme::*;
 use crate::utils::*;
 use bevy::prelude::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Next,
+    Hold,
+}
+
+impl PreviewKind {
+    pub fn config<'a>(&self, game_lib: &'a GameLib) -> &'a PreviewConfig {
+        match self {
+            PreviewKind::Next => &game_lib.config.preview_config,
+            PreviewKind::Hold => &game_lib.config.hold_config,
+        }
+    }
+
+    fn panel_parts(&self) -> (PanelPart, PanelPart) {
+        match self {
+            PreviewKind::Next => (PanelPart::PreviewBackground, PanelPart::PreviewBorder),
+            PreviewKind::Hold => (PanelPart::HoldBackground, PanelPart::HoldBorder),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct Preview {
+    pub kind: PreviewKind,
     pub play_box: PlayBox,
     pub region: PlayBoxRegion,
+    /// Design space offset from the configured preview position.
+    offset: Vec2,
+}
+
+/// The hold slot: a preview panel showing the piece kept aside until it is swapped back.
+#[derive(Resource)]
+pub struct HoldSlot {
+    pub preview: Preview,
+}
+
+impl HoldSlot {
+    pub fn new(
+        commands: &mut Commands,
+        game_lib: &GameLib,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Self {
+        Self {
+            preview: Preview::new(PreviewKind::Hold, commands, game_lib, meshes, materials),
+        }
+    }
 }
 
 impl Preview {
+    #[inline]
     pub fn new(
+        kind: PreviewKind,
+        commands: &mut Commands,
+        game_lib: &GameLib,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Self {
+        Self::new_at(kind, Vec2::ZERO, commands, game_lib, meshes, materials)
+    }
+
+    /// Creates a preview moved by `offset` in design space, for a second board.
+    pub fn new_at(
+        kind: PreviewKind,
+        offset: Vec2,
         commands: &mut Commands,
         game_lib: &GameLib,
         meshes: &mut Assets<Mesh>,
         materials: &mut Assets<ColorMaterial>,
     ) -> Self {
         let preview = Preview {
+            kind,
             play_box: PlayBox::default(),
-            region: Self::get_region(game_lib),
+            region: Self::get_region(kind, offset, game_lib),
+            offset,
         };
 
-        Self::create_panel(commands, game_lib, meshes, materials);
+        Self::create_panel(kind, offset, commands, game_lib, meshes, materials);
 
-        info!("Preview initialized successfully");
+        info!("Preview {:?} initialized successfully", kind);
 
         preview
     }
 
-    pub fn reset_box(
-        &mut self,
-        index_gen: &mut IndexGen,
-        commands: &mut Commands,
-        game_lib: &GameLib,
-    ) {
-        if self.play_box.is_valid() {
-            return;
-        }
-
+    /// Shows `index`, replacing the piece shown before.
+    pub fn show(&mut self, index: &BoxIndex, commands: &mut Commands, game_lib: &GameLib) {
+        self.play_box.despawn(commands);
         self.play_box.init(
-            index_gen.rand_box(),
+            index.clone(),
             BoxPos::new(0, 0),
             &self.region,
             game_lib,
@@ -47,27 +104,34 @@ impl Preview {
         );
     }
 
-    fn get_region(game_lib: &GameLib) -> PlayBoxRegion {
+    pub fn update_layout(&mut self, commands: &mut Commands, game_lib: &GameLib) {
+        self.region.box_origin = Self::get_box_origin(self.kind, self.offset, game_lib);
+        self.play_box
+            .update_pos_vis(&self.region, commands, game_lib, MoveKind::Snap);
+    }
+
+    fn get_region(kind: PreviewKind, offset: Vec2, game_lib: &GameLib) -> PlayBoxRegion {
         PlayBoxRegion::new(
-            Self::get_box_origin(game_lib),
+            Self::get_box_origin(kind, offset, game_lib),
             PLAY_BOX_BITMAP_SIZE,
             PLAY_BOX_BITMAP_SIZE,
         )
     }
 
-    fn get_box_origin(game_lib: &GameLib) -> Vec2 {
-        let preview_config = &game_lib.config.preview_config;
+    fn get_box_origin(kind: PreviewKind, offset: Vec2, game_lib: &GameLib) -> Vec2 {
+        let preview_config = kind.config(game_lib);
         let box_config = &game_lib.config.box_config;
-        game_lib.origin_pos
-            + vec_to_vec2(&preview_config.pos)
+        let design_pos = vec_to_vec2(&preview_config.pos)
+            + offset
             + Vec2::splat(preview_config.border_breath + box_config.spacing)
-            + Vec2::splat(box_config.size) / 2.0
+            + Vec2::splat(box_config.size) / 2.0;
+        game_lib.layout_pos(&design_pos)
     }
 
-    fn get_size(game_lib: &GameLib) -> (RectSize, RectSize) {
+    fn get_size(kind: PreviewKind, game_lib: &GameLib) -> (RectSize, RectSize) {
         let spacing = game_lib.config.box_config.spacing;
-        let box_span = game_lib.box_span;
-        let preview_config = &game_lib.config.preview_config;
+        let box_span = game_lib.design_box_span();
+        let preview_config = kind.config(game_lib);
 
         let internal_size = RectSize {
             width: (PLAY_BOX_BITMAP_SIZE as f32) * box_span + spacing,
@@ -83,36 +147,48 @@ impl Preview {
     }
 
     fn create_panel(
+        kind: PreviewKind,
+        offset: Vec2,
         commands: &mut Commands,
         game_lib: &GameLib,
         meshes: &mut Assets<Mesh>,
         materials: &mut Assets<ColorMaterial>,
     ) {
-        let preview_config = &game_lib.config.preview_config;
-        let (internal_size, total_size) = Self::get_size(game_lib);
+        if game_lib.headless {
+            return;
+        }
+
+        let preview_config = kind.config(game_lib);
+        let (background_part, border_part) = kind.panel_parts();
+        let (internal_size, total_size) = Self::get_size(kind, game_lib);
         let background_color = vec_to_color(&preview_config.background_color);
         let border_color = vec_to_color(&preview_config.border_color);
-        let pos = game_lib.origin_pos
-            + vec_to_vec2(&preview_config.pos)
+        let anchor = vec_to_vec2(&preview_config.pos)
+            + offset
             + Vec2::new(total_size.width, total_size.height) / 2.0;
 
-        create_rect(
-            &pos,
+        let background = create_anchored_rect(
+            anchor,
             preview_config.background_z,
             &internal_size,
             background_color,
+            game_lib,
             commands,
             meshes,
             materials,
         );
-        create_rect(
-            &pos,
+        commands.entity(background).insert(background_part);
+
+        let border = create_anchored_rect(
+            anchor,
             preview_config.border_z,
             &total_size,
             border_color,
+            game_lib,
             commands,
             meshes,
             materials,
         );
+        commands.entity(border).insert(border_part);
     }
 }
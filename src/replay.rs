@@ -0,0 +1,387 @@
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+/// Seconds skipped by a single seek in playback.
+pub const REPLAY_SEEK_STEP: f32 = 10.0;
+/// Virtual time speed used to fast-forward while seeking.
+const REPLAY_SEEK_SPEED: f32 = 200.0;
+
+/// Gameplay actions applied during one fixed tick.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ActionFrame {
+    pub actions: Vec<InputAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_col: Option<i32>,
+}
+
+impl ActionFrame {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty() && self.target_col.is_none()
+    }
+
+    #[inline]
+    pub fn contains(&self, action: InputAction) -> bool {
+        self.actions.contains(&action)
+    }
+
+    pub fn merge(&mut self, other: &ActionFrame) {
+        for action in other.actions.iter() {
+            if !self.actions.contains(action) {
+                self.actions.push(*action);
+            }
+        }
+        if other.target_col.is_some() {
+            self.target_col = other.target_col;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplayEvent {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub frame: ActionFrame,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Replay {
+    /// FNV-1a hash of the config file the game was played with.
+    pub config_hash: String,
+    pub seed: u64,
+    pub tick_rate: f64,
+    /// Non-empty action frames, ordered by tick.
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn new(config_hash: String, seed: u64, tick_rate: f64) -> Self {
+        Self {
+            config_hash,
+            seed,
+            tick_rate,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let replay: Replay = read_json(path.as_ref())?;
+        info!(
+            "Loaded replay {} with {} events",
+            path.as_ref().display(),
+            replay.events.len()
+        );
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MyError> {
+        write_json(self, path.as_ref())?;
+        info!("Saved replay to {}", path.as_ref().display());
+        Ok(())
+    }
+}
+
+pub fn config_hash<P: AsRef<Path>>(path: P) -> Result<String, MyError> {
+    let bytes = fs::read(path)?;
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
+
+/// Number of fixed gameplay ticks run since the game started.
+#[derive(Resource, Debug, Default)]
+pub struct GameClock {
+    pub tick: u64,
+}
+
+/// Player actions collected from the frames since the last fixed tick.
+#[derive(Resource, Debug, Default)]
+pub struct PendingActions(pub ActionFrame);
+
+/// Actions the gameplay systems act on during the current fixed tick.
+#[derive(Resource, Debug, Default)]
+pub struct TickActions(pub ActionFrame);
+
+#[derive(Resource, Debug)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+}
+
+#[derive(Resource, Debug)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    next_event: usize,
+    speed: f32,
+    seek_target: Option<u64>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next_event: 0,
+            speed: 1.0,
+            seek_target: None,
+        }
+    }
+
+    /// Returns the actions recorded for `tick`, which must not go backwards between
+    /// calls unless the player has been rewound.
+    pub fn frame_at(&mut self, tick: u64) -> ActionFrame {
+        let events = &self.replay.events;
+        while self.next_event < events.len() && events[self.next_event].tick < tick {
+            self.next_event += 1;
+        }
+
+        match events.get(self.next_event) {
+            Some(event) if event.tick == tick => {
+                self.next_event += 1;
+                event.frame.clone()
+            }
+            _ => ActionFrame::default(),
+        }
+    }
+
+    pub fn rewind(&mut self) {
+        self.next_event = 0;
+    }
+
+    #[inline]
+    pub fn is_seeking(&self) -> bool {
+        self.seek_target.is_some()
+    }
+}
+
+/// Collects the triggered gameplay actions of this frame for the next fixed tick.
+pub fn queue_actions(actions: Res<ActionState>, mut pending: ResMut<PendingActions>) {
+    pending.0.merge(&actions.frame());
+}
+
+pub fn begin_tick(
+    mut clock: ResMut<GameClock>,
+    mut pending: ResMut<PendingActions>,
+    mut tick_actions: ResMut<TickActions>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    player: Option<ResMut<ReplayPlayer>>,
+) {
+    if let Some(mut player) = player {
+        tick_actions.0 = player.frame_at(clock.tick);
+        pending.0 = ActionFrame::default();
+    } else {
+        tick_actions.0 = std::mem::take(&mut pending.0);
+        if let Some(mut recorder) = recorder
+            && !tick_actions.0.is_empty()
+        {
+            recorder.replay.events.push(ReplayEvent {
+                tick: clock.tick,
+                frame: tick_actions.0.clone(),
+            });
+        }
+    }
+
+    clock.tick += 1;
+}
+
+/// Applies pending state changes right away, so that every fixed tick sees the state
+/// set by the previous one no matter how many ticks run in a frame.
+pub fn apply_tick_transitions(world: &mut World) {
+    let _ = world.try_run_schedule(StateTransition);
+}
+
+/// Holds the simulation once a seek has reached its target tick.
+pub fn tick_allowed(clock: Res<GameClock>, player: Option<Res<ReplayPlayer>>) -> bool {
+    match player.and_then(|p| p.seek_target) {
+        Some(target) => clock.tick < target,
+        None => true,
+    }
+}
+
+pub fn save_replay(clock: Res<GameClock>, recorder: Option<Res<ReplayRecorder>>) {
+    let Some(recorder) = recorder else {
+        return;
+    };
+
+    let path = user_data_dir()
+        .join("replays")
+        .join(format!("replay-{}.json", unix_time()));
+    if let Err(err) = recorder.replay.save(&path) {
+        error!("Failed to save replay: {}", err);
+    } else {
+        info!("Replay covers {} ticks", clock.tick);
+    }
+}
+
+/// Playback controls: `1`, `2` and `4` set the speed, `[` and `]` seek backwards and
+/// forwards. Pausing uses the regular pause action.
+pub fn control_playback(
+    keys: Res<ButtonInput<KeyCode>>,
+    clock: Res<GameClock>,
+    app_state: Res<State<AppState>>,
+    mut player: ResMut<ReplayPlayer>,
+    mut time: ResMut<Time<Virtual>>,
+    mut restart: MessageWriter<RestartGame>,
+) {
+    for (key, speed) in [
+        (KeyCode::Digit1, 1.0),
+        (KeyCode::Digit2, 2.0),
+        (KeyCode::Digit4, 4.0),
+    ] {
+        if keys.just_pressed(key) {
+            player.speed = speed;
+            info!("Playback speed {}x", speed);
+        }
+    }
+
+    let step = (REPLAY_SEEK_STEP as f64 * player.replay.tick_rate) as u64;
+    let seek = if keys.just_pressed(KeyCode::BracketLeft) {
+        Some(clock.tick.saturating_sub(step))
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        Some(clock.tick + step)
+    } else {
+        None
+    };
+
+    if let Some(target) = seek {
+        info!("Seeking to tick {}", target);
+        if target < clock.tick {
+            // Ticks cannot be undone, so re-simulate the game from the start.
+            player.rewind();
+            restart.write(RestartGame);
+        }
+        player.seek_target = Some(target);
+    }
+
+    // A game that ends before the target stops ticking, so the seek ends with it.
+    if let Some(target) = player.seek_target
+        && (clock.tick >= target || *app_state.get() == AppState::Stopped)
+        && seek.is_none_or(|t| t >= clock.tick)
+    {
+        player.seek_target = None;
+    }
+
+    if player.is_seeking() {
+        time.set_relative_speed(REPLAY_SEEK_SPEED);
+        time.set_max_delta(Duration::from_secs(10));
+    } else {
+        time.set_relative_speed(player.speed);
+        time.set_max_delta(Duration::from_millis(250));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_lib::*;
+    use crate::play_box::*;
+    use crate::rules::*;
+    use rand::prelude::*;
+
+    const SEED: u64 = 5;
+    const TICKS: u64 = 3000;
+
+    fn game_lib() -> GameLib {
+        GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap()
+    }
+
+    fn tick_game(actions: Res<TickActions>, game_lib: Res<GameLib>, mut game: ResMut<Game>) {
+        game.tick(&actions.0, game_lib.as_ref());
+    }
+
+    /// An app running the fixed tick of the game, `begin_tick` followed by the rules,
+    /// once per update.
+    fn game_app() -> App {
+        let game_lib = game_lib();
+        let config = &game_lib.config;
+        let index_gen = IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            SEED,
+        );
+        let game = Game(GameRules::new(&game_lib, index_gen, config.tick_rate));
+
+        let mut app = App::new();
+        app.init_resource::<GameClock>()
+            .init_resource::<PendingActions>()
+            .init_resource::<TickActions>()
+            .insert_resource(game)
+            .insert_resource(game_lib)
+            .add_systems(Update, (begin_tick, tick_game).chain());
+        app
+    }
+
+    #[test]
+    fn played_back_replays_end_on_the_recorded_board() {
+        let actions = [
+            InputAction::MoveLeft,
+            InputAction::MoveRight,
+            InputAction::RotateCw,
+            InputAction::RotateCcw,
+            InputAction::SoftDrop,
+            InputAction::HardDrop,
+            InputAction::Hold,
+        ];
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut app = game_app();
+        app.insert_resource(ReplayRecorder {
+            replay: Replay::new(String::new(), SEED, 64.0),
+        });
+        for _ in 0..TICKS {
+            if rng.random_bool(0.2) {
+                let action = *actions.choose(&mut rng).unwrap();
+                app.world_mut().resource_mut::<PendingActions>().0 = ActionFrame {
+                    actions: vec![action],
+                    target_col: None,
+                };
+            }
+            app.update();
+        }
+        let recorded = app.world().resource::<Game>();
+        assert!(recorded.pieces() > 10);
+
+        let replay = &app.world().resource::<ReplayRecorder>().replay;
+        let json = serde_json::to_string(replay).unwrap();
+        let mut playback = game_app();
+        playback.insert_resource(ReplayPlayer::new(serde_json::from_str(&json).unwrap()));
+        for _ in 0..TICKS {
+            playback.update();
+        }
+
+        let played = playback.world().resource::<Game>();
+        assert_eq!(played.board.cells(), recorded.board.cells());
+        assert_eq!(played.scoreboard.score, recorded.scoreboard.score);
+        assert_eq!(played.pieces(), recorded.pieces());
+    }
+
+    #[test]
+    fn seeking_past_the_end_of_the_game_stops_at_game_over() {
+        let mut keys = ButtonInput::<KeyCode>::default();
+        keys.press(KeyCode::BracketRight);
+        let mut app = App::new();
+        app.add_message::<RestartGame>()
+            .init_resource::<Time<Virtual>>()
+            .insert_resource(keys)
+            .insert_resource(GameClock { tick: 900 })
+            .insert_resource(State::new(AppState::Stopped))
+            .insert_resource(ReplayPlayer::new(Replay::new(String::new(), SEED, 64.0)))
+            .add_systems(Update, control_playback);
+        app.update();
+
+        assert!(!app.world().resource::<ReplayPlayer>().is_seeking());
+        assert_eq!(
+            app.world().resource::<Time<Virtual>>().relative_speed(),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,763 @@
+use crate::board::*;
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::input::*;
+use crate::play_box::*;
+use crate::replay::*;
+use crate::score::*;
+
+/// Where a game stands between two ticks. The windowed game mirrors it with its
+/// `AppState`, the other front ends only read it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// The next piece enters the board on the coming tick.
+    Spawning,
+    /// The piece falls with gravity and takes player input.
+    Falling,
+    /// A soft drop is moving the piece down; input waits until it ends.
+    FastDown,
+    /// Full rows are shown before they are removed.
+    Clearing,
+    /// The rows above removed lines are falling into place.
+    Collapsing,
+    Over,
+}
+
+/// What happened during a tick, in order, so front ends can follow the game without
+/// comparing states.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleEvent {
+    /// The next piece entered the board at this position and a new one was drawn.
+    Spawned(BoxPos),
+    /// The player moved the piece sideways.
+    Moved(BoxPos),
+    /// The piece moved down, by gravity or a drop.
+    Dropped(BoxPos),
+    Rotated(Rotation),
+    /// The piece went to the hold slot; the held piece came back at this position, if
+    /// there was one.
+    Held(Option<BoxPos>),
+    Locked,
+    LinesCleared(usize),
+    LevelUp(u32),
+}
+
+/// Durations of the game, counted in ticks of a fixed length.
+#[derive(Debug, Clone)]
+struct TickTiming {
+    gravity: u32,
+    fast_down: u32,
+    fast_down_steps: u32,
+    line_clear: u32,
+    tick_secs: f32,
+}
+
+impl TickTiming {
+    fn new(game_lib: &GameLib, tick_rate: f64) -> Self {
+        let config = &game_lib.config;
+        let ticks = |secs: f32| ((secs as f64 * tick_rate).ceil() as u32).max(1);
+        Self {
+            gravity: ticks(config.drop_down_interval),
+            fast_down: ticks(config.fast_down_interval),
+            fast_down_steps: config.fast_down_max_steps,
+            line_clear: ticks(config.line_clear_config.duration()),
+            tick_secs: (1.0 / tick_rate) as f32,
+        }
+    }
+}
+
+/// The rules of a single game on a pure [`Board`], advanced one fixed tick at a time.
+/// The windowed, terminal, headless and versus games, the simulation and the bot play
+/// through it, so they cannot drift apart.
+#[derive(Debug, Clone)]
+pub struct GameRules {
+    pub board: Board,
+    index_gen: IndexGen,
+    piece: Option<BoxIndex>,
+    pos: BoxPos,
+    next: BoxIndex,
+    hold: Option<BoxIndex>,
+    can_hold: bool,
+    pub scoreboard: Scoreboard,
+    phase: Phase,
+    timing: TickTiming,
+    /// Ticks since gravity last moved the piece.
+    fall_wait: u32,
+    /// Ticks left in the current wait: until the next soft drop step, the end of the
+    /// line clear or the end of the collapse.
+    wait: u32,
+    fast_down_steps: u32,
+    /// Garbage rows received and not added to the board yet, in batches with the column
+    /// each one leaves open.
+    garbage: Vec<(u32, usize)>,
+    pieces: u64,
+    ticks: u64,
+}
+
+impl GameRules {
+    /// Starts a game drawing from `index_gen`, played at `tick_rate` ticks per second.
+    /// The first piece spawns on the first tick.
+    pub fn new(game_lib: &GameLib, mut index_gen: IndexGen, tick_rate: f64) -> Self {
+        let panel_config = &game_lib.config.game_panel_config;
+        let next = index_gen.rand_box();
+        Self {
+            board: Board::new(
+                panel_config.row_count(),
+                panel_config.col_count(),
+                panel_config.main_rows,
+            ),
+            index_gen,
+            piece: None,
+            pos: BoxPos::default(),
+            next,
+            hold: None,
+            can_hold: true,
+            scoreboard: Scoreboard::default(),
+            phase: Phase::Spawning,
+            timing: TickTiming::new(game_lib, tick_rate),
+            fall_wait: 0,
+            wait: 0,
+            fast_down_steps: 0,
+            garbage: Vec::new(),
+            pieces: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Throws the game away and starts over with the same piece sequence.
+    pub fn restart(&mut self, game_lib: &GameLib) {
+        self.board.clear();
+        self.index_gen = IndexGen::with_seed(
+            game_lib.config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            self.index_gen.seed(),
+        );
+        self.next = self.index_gen.rand_box();
+        self.piece = None;
+        self.hold = None;
+        self.can_hold = true;
+        self.scoreboard = Scoreboard::default();
+        self.phase = Phase::Spawning;
+        self.fall_wait = 0;
+        self.wait = 0;
+        self.garbage.clear();
+        self.pieces = 0;
+        self.ticks = 0;
+    }
+
+    /// Continues a saved game drawing from `index_gen`. `piece` keeps falling where it
+    /// was, `fall_wait` ticks into the gravity interval; without one the next piece
+    /// spawns on the first tick.
+    pub fn resume(
+        &mut self,
+        index_gen: IndexGen,
+        piece: Option<(BoxIndex, BoxPos)>,
+        next: BoxIndex,
+        hold: Option<BoxIndex>,
+        can_hold: bool,
+        fall_wait: u32,
+    ) {
+        self.index_gen = index_gen;
+        self.phase = if piece.is_some() {
+            Phase::Falling
+        } else {
+            Phase::Spawning
+        };
+        if let Some((index, pos)) = piece {
+            self.piece = Some(index);
+            self.pos = pos;
+        }
+        self.next = next;
+        self.hold = hold;
+        self.can_hold = can_hold;
+        self.fall_wait = fall_wait.min(self.timing.gravity - 1);
+    }
+
+    #[inline]
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    #[inline]
+    pub fn is_over(&self) -> bool {
+        self.phase == Phase::Over
+    }
+
+    #[inline]
+    pub fn index_gen(&self) -> &IndexGen {
+        &self.index_gen
+    }
+
+    /// The falling piece and its position.
+    pub fn piece(&self) -> Option<(&BoxIndex, &BoxPos)> {
+        self.piece.as_ref().map(|index| (index, &self.pos))
+    }
+
+    #[inline]
+    pub fn next(&self) -> &BoxIndex {
+        &self.next
+    }
+
+    #[inline]
+    pub fn hold(&self) -> Option<&BoxIndex> {
+        self.hold.as_ref()
+    }
+
+    #[inline]
+    pub fn can_hold(&self) -> bool {
+        self.can_hold
+    }
+
+    /// Pieces that have entered the board so far.
+    #[inline]
+    pub fn pieces(&self) -> u64 {
+        self.pieces
+    }
+
+    /// Ticks played so far, not counting those after the game ended.
+    #[inline]
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    #[inline]
+    pub fn fall_wait(&self) -> u32 {
+        self.fall_wait
+    }
+
+    #[inline]
+    pub fn tick_secs(&self) -> f32 {
+        self.timing.tick_secs
+    }
+
+    /// Garbage rows waiting to be added to the board.
+    pub fn incoming_garbage(&self) -> u32 {
+        self.garbage.iter().map(|(rows, _)| rows).sum()
+    }
+
+    /// Queues `rows` garbage rows open at `hole_col`. They push up the stack the next
+    /// time a piece locks without clearing lines.
+    pub fn receive_garbage(&mut self, rows: u32, hole_col: usize) {
+        if rows > 0 {
+            self.garbage.push((rows, hole_col));
+        }
+    }
+
+    /// Cancels up to `rows` waiting garbage rows, oldest first, and returns the rows
+    /// left over.
+    pub fn cancel_garbage(&mut self, mut rows: u32) -> u32 {
+        while rows > 0 {
+            let Some((waiting, _)) = self.garbage.first_mut() else {
+                break;
+            };
+            let cancelled = rows.min(*waiting);
+            *waiting -= cancelled;
+            rows -= cancelled;
+            if *waiting == 0 {
+                self.garbage.remove(0);
+            }
+        }
+        rows
+    }
+
+    /// Advances the game by one tick. While the piece is falling, at most one action of
+    /// `frame` is applied, in the order the game has always used, then gravity.
+    pub fn tick(&mut self, frame: &ActionFrame, game_lib: &GameLib) -> Vec<RuleEvent> {
+        let mut events = Vec::new();
+        if self.is_over() {
+            return events;
+        }
+
+        self.ticks += 1;
+        match self.phase {
+            Phase::Spawning => self.spawn(game_lib, &mut events),
+            Phase::Falling => {
+                self.apply_input(frame, game_lib, &mut events);
+                if self.piece.is_some() {
+                    self.fall(game_lib, &mut events);
+                }
+            }
+            Phase::FastDown => self.fast_down(game_lib, &mut events),
+            Phase::Clearing => {
+                self.wait = self.wait.saturating_sub(1);
+                if self.wait == 0 {
+                    let rows = self.board.remove_full_rows();
+                    self.wait = game_lib
+                        .config
+                        .collapse_config
+                        .ticks(rows, self.timing.tick_secs);
+                    self.phase = if self.wait == 0 {
+                        Phase::Spawning
+                    } else {
+                        Phase::Collapsing
+                    };
+                }
+            }
+            Phase::Collapsing => {
+                self.wait = self.wait.saturating_sub(1);
+                if self.wait == 0 {
+                    self.phase = Phase::Spawning;
+                }
+            }
+            Phase::Over => {}
+        }
+        events
+    }
+
+    /// Locks the falling piece at `placement` in a single tick, as if it had been moved
+    /// there and hard dropped.
+    pub fn place(&mut self, placement: &Placement, game_lib: &GameLib) -> Vec<RuleEvent> {
+        let mut events = Vec::new();
+        if self.phase != Phase::Falling || self.piece.is_none() {
+            return events;
+        }
+
+        self.ticks += 1;
+        self.piece = Some(placement.index.clone());
+        self.pos = placement.pos.clone();
+        self.lock(game_lib, &mut events);
+        events
+    }
+
+    fn apply_input(
+        &mut self,
+        frame: &ActionFrame,
+        game_lib: &GameLib,
+        events: &mut Vec<RuleEvent>,
+    ) {
+        let rotation = if frame.contains(InputAction::RotateCw) {
+            Some(Rotation::Cw)
+        } else if frame.contains(InputAction::RotateCcw) {
+            Some(Rotation::Ccw)
+        } else if frame.contains(InputAction::Rotate180) {
+            Some(Rotation::Half)
+        } else {
+            None
+        };
+
+        if let Some(col) = frame.target_col {
+            self.move_to_col(col, game_lib, events);
+        } else if frame.contains(InputAction::MoveLeft) {
+            self.shift(-1, game_lib, events);
+        } else if frame.contains(InputAction::MoveRight) {
+            self.shift(1, game_lib, events);
+        } else if let Some(rotation) = rotation {
+            self.rotate(rotation, game_lib, events);
+        } else if frame.contains(InputAction::HardDrop) {
+            self.hard_drop(game_lib, events);
+        } else if frame.contains(InputAction::SoftDrop) {
+            if self.can_move_to(&self.below(), game_lib) {
+                self.phase = Phase::FastDown;
+                self.wait = self.timing.fast_down;
+                self.fast_down_steps = 0;
+            }
+        } else if frame.contains(InputAction::Hold) {
+            self.try_hold(game_lib, events);
+        }
+    }
+
+    fn fall(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        self.fall_wait += 1;
+        if self.fall_wait < self.timing.gravity {
+            return;
+        }
+
+        self.fall_wait = 0;
+        let below = self.below();
+        if self.can_move_to(&below, game_lib) {
+            self.pos = below;
+            events.push(RuleEvent::Dropped(self.pos.clone()));
+        } else {
+            self.lock(game_lib, events);
+        }
+    }
+
+    /// Moves the piece one row every fast down interval, until it is blocked or has
+    /// made the configured number of steps. It never locks by itself.
+    fn fast_down(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let mut stop = self.piece.is_none();
+        if !stop && self.fast_down_steps < self.timing.fast_down_steps {
+            self.wait = self.wait.saturating_sub(1);
+            if self.wait == 0 {
+                self.wait = self.timing.fast_down;
+                self.fast_down_steps += 1;
+                let below = self.below();
+                if self.can_move_to(&below, game_lib) {
+                    self.pos = below;
+                    events.push(RuleEvent::Dropped(self.pos.clone()));
+                } else {
+                    stop = true;
+                }
+            }
+        }
+
+        if stop || self.fast_down_steps >= self.timing.fast_down_steps {
+            self.phase = Phase::Falling;
+        }
+    }
+
+    fn below(&self) -> BoxPos {
+        BoxPos::new(self.pos.row - 1, self.pos.col)
+    }
+
+    fn can_move_to(&self, pos: &BoxPos, game_lib: &GameLib) -> bool {
+        match &self.piece {
+            Some(index) => self.board.can_place(pos, game_lib.box_pos(index)),
+            None => false,
+        }
+    }
+
+    fn shift(&mut self, step: i32, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let pos = BoxPos::new(self.pos.row, self.pos.col + step);
+        if self.can_move_to(&pos, game_lib) {
+            self.pos = pos;
+            events.push(RuleEvent::Moved(self.pos.clone()));
+        }
+    }
+
+    /// Moves the piece sideways until its middle column reaches `col` or it is blocked.
+    fn move_to_col(&mut self, col: i32, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let Some(index) = &self.piece else {
+            return;
+        };
+
+        let cols = game_lib.box_pos(index).iter().map(|p| p.col);
+        let (min_col, max_col) =
+            cols.fold((i32::MAX, i32::MIN), |(lo, hi), c| (lo.min(c), hi.max(c)));
+        let middle = (min_col + max_col) / 2;
+        let step = (col - (self.pos.col + middle)).signum();
+
+        let mut pos = self.pos.clone();
+        while pos.col + middle != col {
+            let next = BoxPos::new(pos.row, pos.col + step);
+            if !self.can_move_to(&next, game_lib) {
+                break;
+            }
+            pos = next;
+        }
+
+        if pos.col != self.pos.col {
+            self.pos = pos;
+            events.push(RuleEvent::Moved(self.pos.clone()));
+        }
+    }
+
+    fn rotate(&mut self, rotation: Rotation, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let Some(index) = &mut self.piece else {
+            return;
+        };
+
+        let mut rotated = index.clone();
+        rotated.rotate(rotation);
+        if self.board.can_place(&self.pos, game_lib.box_pos(&rotated)) {
+            *index = rotated;
+            events.push(RuleEvent::Rotated(rotation));
+        }
+    }
+
+    fn hard_drop(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let Some(index) = &self.piece else {
+            return;
+        };
+
+        let pos = self.board.drop_pos(&self.pos, game_lib.box_pos(index));
+        if pos != self.pos {
+            self.pos = pos;
+            events.push(RuleEvent::Dropped(self.pos.clone()));
+        }
+        self.lock(game_lib, events);
+    }
+
+    /// Swaps the piece with the hold slot. With an empty slot the next piece spawns on
+    /// the coming tick. Gravity keeps its pace either way.
+    fn try_hold(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        if !self.can_hold {
+            return;
+        }
+
+        let Some(index) = self.piece.clone() else {
+            return;
+        };
+
+        match self.hold.clone() {
+            Some(held) => {
+                let Some(pos) = init_box_pos(&self.board, &held, game_lib) else {
+                    return;
+                };
+                self.piece = Some(held);
+                self.pos = pos.clone();
+                events.push(RuleEvent::Held(Some(pos)));
+            }
+            None => {
+                self.piece = None;
+                self.phase = Phase::Spawning;
+                events.push(RuleEvent::Held(None));
+            }
+        }
+
+        self.hold = Some(index);
+        self.can_hold = false;
+    }
+
+    fn lock(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let Some(index) = self.piece.take() else {
+            return;
+        };
+
+        if !self
+            .board
+            .place(&self.pos, game_lib.box_pos(&index), index.type_index)
+        {
+            panic!(
+                "Failed to lock piece: row={} col={} is blocked",
+                self.pos.row, self.pos.col
+            );
+        }
+        self.can_hold = true;
+        self.fall_wait = 0;
+        events.push(RuleEvent::Locked);
+
+        if self.board.has_full_rows() {
+            let count = self.board.full_rows().len();
+            events.push(RuleEvent::LinesCleared(count));
+            if let Some(level) = self
+                .scoreboard
+                .add_lines(count, &game_lib.config.score_config)
+            {
+                events.push(RuleEvent::LevelUp(level));
+            }
+            self.phase = Phase::Clearing;
+            self.wait = self.timing.line_clear;
+            return;
+        }
+
+        let garbage_type = game_lib.config.box_config.garbage_type_index();
+        for (rows, hole_col) in self.garbage.drain(..) {
+            self.board
+                .add_garbage(rows as usize, hole_col, garbage_type);
+        }
+        self.phase = if self.board.reach_top() {
+            Phase::Over
+        } else {
+            Phase::Spawning
+        };
+    }
+
+    /// Puts the next piece at the top of the board and draws a new one, or ends the
+    /// game if it does not fit.
+    fn spawn(&mut self, game_lib: &GameLib, events: &mut Vec<RuleEvent>) {
+        let Some(pos) = init_box_pos(&self.board, &self.next, game_lib) else {
+            self.phase = Phase::Over;
+            return;
+        };
+
+        let next = self.index_gen.rand_box();
+        self.piece = Some(std::mem::replace(&mut self.next, next));
+        self.pos = pos.clone();
+        self.pieces += 1;
+        self.phase = Phase::Falling;
+        events.push(RuleEvent::Spawned(pos));
+    }
+}
+
+/// Where a new piece appears on the board: centered at the top of the main rows, or as
+/// low above them as it fits. Returns `None` when it fits nowhere.
+pub fn init_box_pos(board: &Board, index: &BoxIndex, game_lib: &GameLib) -> Option<BoxPos> {
+    let box_size = game_lib.box_size(index);
+    let max_row = board.row_count() as i32 - box_size.height as i32;
+    let init_row = board.main_rows() as i32 - box_size.height as i32;
+    let col = (board.col_count() as i32 - box_size.width as i32) / 2;
+
+    (init_row..=max_row)
+        .map(|row| BoxPos::new(row, col))
+        .find(|pos| board.can_place(pos, game_lib.box_pos(index)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Assets;
+
+    fn game_lib() -> GameLib {
+        GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap()
+    }
+
+    fn new_rules(game_lib: &GameLib) -> GameRules {
+        let config = &game_lib.config;
+        let type_count = config.box_config.play_box_type_count();
+        let index_gen = IndexGen::with_seed(type_count, PLAY_BOX_ROTATE_COUNT, 7);
+        GameRules::new(game_lib, index_gen, config.tick_rate)
+    }
+
+    fn frame(action: InputAction) -> ActionFrame {
+        ActionFrame {
+            actions: vec![action],
+            target_col: None,
+        }
+    }
+
+    fn piece_pos(rules: &GameRules) -> BoxPos {
+        rules.piece().unwrap().1.clone()
+    }
+
+    #[test]
+    fn pieces_spawn_on_their_own_tick_and_fall_once_per_interval() {
+        let game_lib = game_lib();
+        let config = &game_lib.config;
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        let next = rules.next().clone();
+
+        let events = rules.tick(&idle, &game_lib);
+        assert!(matches!(events.as_slice(), [RuleEvent::Spawned(_)]));
+        assert_eq!(rules.piece().unwrap().0, &next);
+        assert_eq!(rules.phase(), Phase::Falling);
+
+        let pos = piece_pos(&rules);
+        let gravity_ticks = (config.drop_down_interval as f64 * config.tick_rate) as u32;
+        for _ in 1..gravity_ticks {
+            assert!(rules.tick(&idle, &game_lib).is_empty());
+        }
+        let below = BoxPos::new(pos.row - 1, pos.col);
+        assert_eq!(
+            rules.tick(&idle, &game_lib),
+            vec![RuleEvent::Dropped(below)]
+        );
+    }
+
+    #[test]
+    fn soft_drop_moves_down_without_locking() {
+        let game_lib = game_lib();
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        rules.tick(&idle, &game_lib);
+        let pos = piece_pos(&rules);
+
+        rules.tick(&frame(InputAction::SoftDrop), &game_lib);
+        assert_eq!(rules.phase(), Phase::FastDown);
+        // Input waits while the piece moves down.
+        while rules.phase() == Phase::FastDown {
+            rules.tick(&frame(InputAction::MoveLeft), &game_lib);
+        }
+
+        let steps = game_lib.config.fast_down_max_steps as i32;
+        assert_eq!(piece_pos(&rules), BoxPos::new(pos.row - steps, pos.col));
+        assert_eq!(rules.phase(), Phase::Falling);
+    }
+
+    #[test]
+    fn hard_drop_locks_in_the_same_tick() {
+        let game_lib = game_lib();
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        rules.tick(&idle, &game_lib);
+
+        let events = rules.tick(&frame(InputAction::HardDrop), &game_lib);
+        assert_eq!(events.last(), Some(&RuleEvent::Locked));
+        assert!(rules.piece().is_none());
+        assert_eq!(rules.phase(), Phase::Spawning);
+
+        rules.tick(&idle, &game_lib);
+        assert_eq!(rules.pieces(), 2);
+    }
+
+    #[test]
+    fn full_rows_are_shown_then_removed() {
+        let game_lib = game_lib();
+        let config = &game_lib.config;
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        rules.tick(&idle, &game_lib);
+
+        // Fill the bottom row around the cells the piece lands on.
+        let (index, pos) = rules.piece().unwrap();
+        let cells = game_lib.box_pos(index);
+        let landing = rules.board.drop_pos(pos, cells);
+        for col in 0..rules.board.col_count() as i32 {
+            let covered = cells
+                .iter()
+                .any(|c| landing.row + c.row == 0 && landing.col + c.col == col);
+            if !covered {
+                rules
+                    .board
+                    .place(&BoxPos::new(0, col), &[BoxPos::default()], 0);
+            }
+        }
+
+        let events = rules.tick(&frame(InputAction::HardDrop), &game_lib);
+        assert!(events.contains(&RuleEvent::LinesCleared(1)));
+        assert_eq!(rules.scoreboard.lines, 1);
+        assert_eq!(rules.phase(), Phase::Clearing);
+
+        let clear_ticks = (config.line_clear_config.duration() as f64 * config.tick_rate).ceil();
+        for _ in 1..clear_ticks as u32 {
+            rules.tick(&idle, &game_lib);
+            assert_eq!(rules.board.full_rows().len(), 1);
+        }
+        rules.tick(&idle, &game_lib);
+        assert!(rules.board.full_rows().is_empty());
+        assert_ne!(rules.phase(), Phase::Clearing);
+
+        while rules.phase() != Phase::Falling {
+            rules.tick(&idle, &game_lib);
+        }
+        assert_eq!(rules.pieces(), 2);
+    }
+
+    #[test]
+    fn a_piece_can_be_held_once_per_drop() {
+        let game_lib = game_lib();
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        rules.tick(&idle, &game_lib);
+        let first = rules.piece().unwrap().0.clone();
+
+        let events = rules.tick(&frame(InputAction::Hold), &game_lib);
+        assert_eq!(events, vec![RuleEvent::Held(None)]);
+        assert_eq!(rules.hold(), Some(&first));
+        assert_eq!(rules.phase(), Phase::Spawning);
+        rules.tick(&idle, &game_lib);
+        let second = rules.piece().unwrap().0.clone();
+
+        assert!(rules.tick(&frame(InputAction::Hold), &game_lib).is_empty());
+        assert_eq!(rules.piece().unwrap().0, &second);
+
+        rules.tick(&frame(InputAction::HardDrop), &game_lib);
+        rules.tick(&idle, &game_lib);
+        let events = rules.tick(&frame(InputAction::Hold), &game_lib);
+        assert!(matches!(events.as_slice(), [RuleEvent::Held(Some(_))]));
+        assert_eq!(rules.piece().unwrap().0, &first);
+        assert!(!rules.can_hold());
+    }
+
+    #[test]
+    fn garbage_waits_for_a_lock_without_clears() {
+        let game_lib = game_lib();
+        let idle = ActionFrame::default();
+        let mut rules = new_rules(&game_lib);
+        rules.receive_garbage(2, 0);
+        rules.receive_garbage(1, 3);
+        assert_eq!(rules.incoming_garbage(), 3);
+        assert_eq!(rules.cancel_garbage(1), 0);
+        assert_eq!(rules.incoming_garbage(), 2);
+
+        rules.tick(&idle, &game_lib);
+        rules.tick(&frame(InputAction::HardDrop), &game_lib);
+        assert_eq!(rules.incoming_garbage(), 0);
+        assert_eq!(rules.phase(), Phase::Spawning);
+
+        let garbage = Some(game_lib.config.box_config.garbage_type_index());
+        let cells = rules.board.cells();
+        assert_eq!(cells[0][0], garbage);
+        assert_eq!(cells[0][3], None);
+        assert_eq!(cells[1][0], None);
+        assert_eq!(cells[1][3], garbage);
+        assert_eq!(rules.cancel_garbage(4), 4);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::board::*;
+use crate::game_lib::*;
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::play_box::*;
+use crate::rules::*;
+use crate::score::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavedBox {
+    pub index: BoxIndex,
+    pub pos: BoxPos,
+}
+
+/// A game in progress, stored by piece type so that all entities can be rebuilt.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    /// Play box type of every panel cell, indexed `[row][col]` from the bottom row.
+    pub board: Vec<Vec<Option<usize>>>,
+    pub play_box: Option<SavedBox>,
+    pub preview: Option<BoxIndex>,
+    pub hold: Option<BoxIndex>,
+    pub can_hold: bool,
+    pub seed: u64,
+    pub draws: u64,
+    /// Seconds elapsed on the drop-down timer.
+    pub drop_down_elapsed: f32,
+    pub scoreboard: Scoreboard,
+}
+
+impl SaveGame {
+    pub fn load<P: AsRef<Path>>(path: P, game_lib: &GameLib) -> Result<Self, MyError> {
+        let save: SaveGame = read_json(path.as_ref())?;
+        save.validate(game_lib)?;
+
+        info!("Loaded saved game from {}", path.as_ref().display());
+
+        Ok(save)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MyError> {
+        write_json(self, path.as_ref())?;
+        info!("Saved game to {}", path.as_ref().display());
+        Ok(())
+    }
+
+    /// Captures the game between two ticks.
+    pub fn new(rules: &GameRules) -> Self {
+        Self {
+            board: rules.board.cells().clone(),
+            play_box: rules.piece().map(|(index, pos)| SavedBox {
+                index: index.clone(),
+                pos: pos.clone(),
+            }),
+            preview: Some(rules.next().clone()),
+            hold: rules.hold().cloned(),
+            can_hold: rules.can_hold(),
+            seed: rules.index_gen().seed(),
+            draws: rules.index_gen().draws(),
+            drop_down_elapsed: rules.fall_wait() as f32 * rules.tick_secs(),
+            scoreboard: rules.scoreboard.clone(),
+        }
+    }
+
+    /// Puts the saved game into freshly created rules. Panel blocks are spawned on the
+    /// next board sync.
+    pub fn restore(&self, rules: &mut GameRules, game_lib: &GameLib) {
+        rules.board.restore(&self.board);
+
+        let mut index_gen = IndexGen::restore(
+            game_lib.config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            self.seed,
+            self.draws,
+        );
+        let next = match &self.preview {
+            Some(index) => index.clone(),
+            None => index_gen.rand_box(),
+        };
+        let piece = self
+            .play_box
+            .as_ref()
+            .map(|saved| (saved.index.clone(), saved.pos.clone()));
+        let fall_wait = (self.drop_down_elapsed / rules.tick_secs()).round() as u32;
+
+        rules.resume(
+            index_gen,
+            piece,
+            next,
+            self.hold.clone(),
+            self.can_hold,
+            fall_wait,
+        );
+        rules.scoreboard = self.scoreboard.clone();
+    }
+
+    fn validate(&self, game_lib: &GameLib) -> Result<(), MyError> {
+        let panel_config = &game_lib.config.game_panel_config;
+        let type_count = game_lib.config.box_config.play_box_type_count();
+        let invalid = |msg: String| Err(MyError::InvalidSave(msg));
+
+        if self.board.len() != panel_config.row_count()
+            || self
+                .board
+                .iter()
+                .any(|row| row.len() != panel_config.col_count())
+        {
+            return invalid("board size does not match the game panel".to_string());
+        }
+
+        let cell_types = self.board.iter().flatten().flatten();
+        let indices = self
+            .play_box
+            .iter()
+            .map(|b| &b.index)
+            .chain(self.preview.iter())
+            .chain(self.hold.iter());
+        if cell_types.copied().any(|t| t >= type_count)
+            || indices
+                .clone()
+                .any(|i| i.type_index >= type_count || i.rotate_index >= PLAY_BOX_ROTATE_COUNT)
+        {
+            return invalid("unknown play box type".to_string());
+        }
+
+        // Restoring a board drops its full rows from the ones left to clear, so they
+        // would stay forever.
+        if self.board.iter().any(|row| row.iter().all(Option::is_some)) {
+            return invalid("board has full rows".to_string());
+        }
+
+        let mut board = Board::new(
+            panel_config.row_count(),
+            panel_config.col_count(),
+            panel_config.main_rows,
+        );
+        board.restore(&self.board);
+        if let Some(saved) = &self.play_box
+            && !board.can_place(&saved.pos, game_lib.box_pos(&saved.index))
+        {
+            return invalid("falling piece does not fit on the board".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+pub fn save_path(game_lib: &GameLib) -> PathBuf {
+    user_data_dir().join(&game_lib.config.save_file)
+}
+
+/// Saves the game and quits when the save action is pressed.
+pub fn save_game(
+    actions: Res<ActionState>,
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    mut exit_app: MessageWriter<AppExit>,
+) {
+    if !actions.just_pressed(InputAction::Save) {
+        return;
+    }
+
+    match SaveGame::new(&game).save(save_path(game_lib.as_ref())) {
+        Ok(()) => {
+            exit_app.write(AppExit::Success);
+        }
+        Err(err) => error!("Failed to save game: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::*;
+
+    fn game_lib() -> GameLib {
+        GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn restored_piece_keeps_falling() {
+        let game_lib = game_lib();
+        let config = &game_lib.config;
+        let new_rules = || {
+            let type_count = config.box_config.play_box_type_count();
+            let index_gen = IndexGen::with_seed(type_count, PLAY_BOX_ROTATE_COUNT, 7);
+            GameRules::new(&game_lib, index_gen, config.tick_rate)
+        };
+        let idle = ActionFrame::default();
+
+        let mut rules = new_rules();
+        for _ in 0..11 {
+            rules.tick(&idle, &game_lib);
+        }
+        let (index, pos) = rules.piece().map(|(i, p)| (i.clone(), p.clone())).unwrap();
+        assert_eq!(rules.fall_wait(), 10);
+
+        let mut restored = new_rules();
+        SaveGame::new(&rules).restore(&mut restored, &game_lib);
+        assert_eq!(restored.piece(), Some((&index, &pos)));
+        assert_eq!(restored.next(), rules.next());
+        assert_eq!(restored.fall_wait(), rules.fall_wait());
+
+        let gravity_ticks = (config.drop_down_interval as f64 * config.tick_rate) as u32;
+        for _ in 1..gravity_ticks - restored.fall_wait() {
+            restored.tick(&idle, &game_lib);
+        }
+        assert_eq!(restored.piece().unwrap().1, &pos);
+        restored.tick(&idle, &game_lib);
+        assert_eq!(
+            restored.piece().unwrap().1,
+            &BoxPos::new(pos.row - 1, pos.col)
+        );
+    }
+
+    #[test]
+    fn saves_that_cannot_resume_are_rejected() {
+        let game_lib = game_lib();
+        let config = &game_lib.config;
+        let type_count = config.box_config.play_box_type_count();
+        let index_gen = IndexGen::with_seed(type_count, PLAY_BOX_ROTATE_COUNT, 7);
+        let mut rules = GameRules::new(&game_lib, index_gen, config.tick_rate);
+        rules.tick(&ActionFrame::default(), &game_lib);
+        assert!(SaveGame::new(&rules).validate(&game_lib).is_ok());
+
+        let mut outside = SaveGame::new(&rules);
+        outside.play_box.as_mut().unwrap().pos = BoxPos::new(200, 50);
+        assert!(matches!(
+            outside.validate(&game_lib),
+            Err(MyError::InvalidSave(_))
+        ));
+
+        let mut blocked = SaveGame::new(&rules);
+        let pos = blocked.play_box.as_ref().unwrap().pos.clone();
+        blocked.play_box.as_mut().unwrap().pos = BoxPos::new(0, pos.col);
+        let col_count = blocked.board[0].len();
+        blocked.board[0] = vec![Some(0); col_count];
+        blocked.board[0][0] = None;
+        assert!(matches!(
+            blocked.validate(&game_lib),
+            Err(MyError::InvalidSave(_))
+        ));
+
+        let mut full_row = SaveGame::new(&rules);
+        full_row.board[0] = vec![Some(0); col_count];
+        assert!(matches!(
+            full_row.validate(&game_lib),
+            Err(MyError::InvalidSave(_))
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::systems::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    #[default]
+    Marathon,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScoreConfig {
+    /// Points for clearing 1, 2, 3, ... lines at once; the last entry is used for
+    /// any larger count.
+    pub line_scores: Vec<u32>,
+    pub lines_per_level: u32,
+}
+
+#[derive(Resource, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Scoreboard {
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// Seconds played, not counting pauses.
+    pub elapsed: f32,
+}
+
+impl Scoreboard {
+    /// Records a line clear and returns the new level if it went up.
+    pub fn add_lines(&mut self, count: usize, config: &ScoreConfig) -> Option<u32> {
+        if count == 0 {
+            return None;
+        }
+
+        let index = (count - 1).min(config.line_scores.len().saturating_sub(1));
+        let points = config.line_scores.get(index).copied().unwrap_or(0);
+        self.score += points * (self.level + 1);
+        self.lines += count as u32;
+
+        let level = self.lines / config.lines_per_level.max(1);
+        if level > self.level {
+            self.level = level;
+            info!("Level up: level={} lines={}", self.level, self.lines);
+            Some(level)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn track_play_time(time: Res<Time>, mut game: ResMut<Game>) {
+    game.scoreboard.elapsed += time.delta_secs();
+}
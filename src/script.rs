@@ -0,0 +1,515 @@
+use crate::board::*;
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::replay::*;
+use crate::systems::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// When a script step happens. Absolute offsets count from the start of the game,
+/// relative ones from the previous step.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptOffset {
+    Tick(u64),
+    Time(f32),
+    AfterTicks(u64),
+    AfterTime(f32),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScriptStep {
+    #[serde(flatten)]
+    pub at: ScriptOffset,
+    #[serde(default)]
+    pub actions: Vec<InputAction>,
+    #[serde(default)]
+    pub target_col: Option<i32>,
+    /// Expected bottom rows of the board, top to bottom, with `.` for an empty cell and
+    /// any other character for an occupied one.
+    #[serde(default)]
+    pub expect: Option<Vec<String>>,
+    /// Ends the game once this step is reached.
+    #[serde(default)]
+    pub quit: bool,
+}
+
+/// Player actions and board assertions to run instead of reading the keyboard.
+///
+/// Scripts are either JSON files
+/// (`{"seed": 7, "steps": [{"tick": 10, "actions": ["move_left"]}]}`) or text files
+/// with one step per line:
+///
+/// ```text
+/// // Comments start with "//"
+/// seed 7                  // piece seed the expected boards were written for
+/// 10 move_left            // at tick 10
+/// +5 rotate_cw hard_drop  // 5 ticks after the previous step
+/// 2.5s soft_drop          // at 2.5 seconds
+/// +0.5s col=3             // half a second later, move to column 3
+/// +30 expect              // followed by the expected bottom rows, '#' for a block
+/// ....##....
+/// ##########
+/// +1 quit
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct InputScript {
+    /// Seeds the piece generator so that the board checks see the same pieces on
+    /// every run. Without it, `--seed` or `seed` from the config file must be set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub steps: Vec<ScriptStep>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoardCheck {
+    pub tick: u64,
+    pub rows: Vec<String>,
+}
+
+/// Board assertions of the running script and their results so far.
+#[derive(Resource, Debug, Default)]
+pub struct ScriptChecks {
+    checks: Vec<BoardCheck>,
+    next_check: usize,
+    quit_tick: Option<u64>,
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MyError> {
+        let path = path.as_ref();
+        let script = if path.extension().is_some_and(|ext| ext == "json") {
+            read_json(path)?
+        } else {
+            Self::parse(&fs::read_to_string(path)?)?
+        };
+
+        info!(
+            "Loaded input script {} with {} steps",
+            path.display(),
+            script.steps.len()
+        );
+
+        Ok(script)
+    }
+
+    /// Parses the line format described on [`InputScript`].
+    pub fn parse(text: &str) -> Result<Self, MyError> {
+        let mut seed: Option<u64> = None;
+        let mut steps: Vec<ScriptStep> = Vec::new();
+        let mut expecting = false;
+
+        for (line_no, line) in text.lines().enumerate() {
+            let invalid =
+                |msg: &str| MyError::InvalidScript(format!("line {}: {}", line_no + 1, msg));
+            let line = line.split("//").next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if expecting
+                && is_grid_row(line)
+                && let Some(rows) = steps.last_mut().and_then(|s| s.expect.as_mut())
+            {
+                rows.push(line.to_string());
+                continue;
+            }
+            expecting = false;
+
+            let mut words = line.split_whitespace();
+            let offset = words.next().unwrap_or("");
+            if offset == "seed" {
+                let value = words.next().and_then(|w| w.parse().ok());
+                if seed.is_some() || value.is_none() || words.next().is_some() {
+                    return Err(invalid("invalid seed"));
+                }
+                seed = value;
+                continue;
+            }
+            let at = parse_offset(offset).ok_or_else(|| invalid("invalid offset"))?;
+            let mut step = ScriptStep {
+                at,
+                actions: Vec::new(),
+                target_col: None,
+                expect: None,
+                quit: false,
+            };
+
+            for word in words {
+                if word == "expect" {
+                    step.expect = Some(Vec::new());
+                    expecting = true;
+                } else if word == "quit" {
+                    step.quit = true;
+                } else if let Some(col) = word.strip_prefix("col=") {
+                    step.target_col = Some(col.parse().map_err(|_| invalid("invalid column"))?);
+                } else {
+                    let action = parse_action(word)
+                        .ok_or_else(|| invalid(&format!("unknown action {}", word)))?;
+                    step.actions.push(action);
+                }
+            }
+
+            steps.push(step);
+        }
+
+        Ok(Self { seed, steps })
+    }
+
+    /// Turns the script into a replay of its actions plus the board checks, resolving
+    /// all offsets to ticks.
+    pub fn compile(
+        &self,
+        config_hash: String,
+        seed: u64,
+        tick_rate: f64,
+    ) -> (Replay, ScriptChecks) {
+        let mut replay = Replay::new(config_hash, seed, tick_rate);
+        let mut checks = ScriptChecks::default();
+        let mut tick = 0;
+
+        for step in self.steps.iter() {
+            let to_ticks = |secs: f32| (secs as f64 * tick_rate).round() as u64;
+            tick = match step.at {
+                ScriptOffset::Tick(t) => t,
+                ScriptOffset::Time(secs) => to_ticks(secs),
+                ScriptOffset::AfterTicks(t) => tick + t,
+                ScriptOffset::AfterTime(secs) => tick + to_ticks(secs),
+            };
+
+            let frame = ActionFrame {
+                actions: step.actions.clone(),
+                target_col: step.target_col,
+            };
+            if !frame.is_empty() {
+                match replay.events.iter_mut().find(|e| e.tick == tick) {
+                    Some(event) => event.frame.merge(&frame),
+                    None => replay.events.push(ReplayEvent { tick, frame }),
+                }
+            }
+
+            if let Some(rows) = &step.expect {
+                checks.checks.push(BoardCheck {
+                    tick,
+                    rows: rows.clone(),
+                });
+            }
+
+            if step.quit && checks.quit_tick.is_none() {
+                checks.quit_tick = Some(tick);
+            }
+        }
+
+        replay.events.sort_by_key(|e| e.tick);
+        checks.checks.sort_by_key(|c| c.tick);
+
+        (replay, checks)
+    }
+}
+
+impl ScriptChecks {
+    /// Verifies the board against the assertions due by `tick` and returns whether the
+    /// script ends at this tick.
+    pub fn run(&mut self, tick: u64, board: &Board) -> bool {
+        while let Some(check) = self.checks.get(self.next_check).cloned()
+            && check.tick <= tick
+        {
+            self.next_check += 1;
+            match check.verify(board) {
+                Ok(()) => {
+                    self.passed += 1;
+                    info!("Board check at tick {} passed", check.tick);
+                }
+                Err(actual) => {
+                    self.failed += 1;
+                    error!(
+                        "Board check at tick {} failed\nexpected:\n{}\nactual:\n{}",
+                        check.tick,
+                        check.rows.join("\n"),
+                        actual.join("\n")
+                    );
+                }
+            }
+        }
+
+        if self.quit_tick.is_some_and(|t| t <= tick) {
+            self.quit_tick = None;
+            info!(
+                "Input script finished: {} board checks passed, {} failed",
+                self.passed, self.failed
+            );
+            return true;
+        }
+        false
+    }
+}
+
+impl BoardCheck {
+    /// Compares the expected rows with the bottom rows of the board, and returns the
+    /// actual rows if they differ.
+    pub fn verify(&self, board: &Board) -> Result<(), Vec<String>> {
+        let actual = board.ascii_rows(self.rows.len());
+        let matches = self.rows.len() <= board.row_count()
+            && self
+                .rows
+                .iter()
+                .zip(actual.iter())
+                .all(|(expected, actual)| {
+                    expected.chars().count() == actual.chars().count()
+                        && expected
+                            .chars()
+                            .zip(actual.chars())
+                            .all(|(e, a)| (e == '.') == (a == '.'))
+                });
+
+        if matches { Ok(()) } else { Err(actual) }
+    }
+}
+
+fn is_grid_row(line: &str) -> bool {
+    line.chars().all(|c| c == '.' || c == '#')
+}
+
+fn parse_offset(word: &str) -> Option<ScriptOffset> {
+    let (relative, value) = match word.strip_prefix('+') {
+        Some(value) => (true, value),
+        None => (false, word),
+    };
+
+    if let Some(secs) = value.strip_suffix('s') {
+        let secs: f32 = secs.parse().ok().filter(|s: &f32| *s >= 0.0)?;
+        Some(if relative {
+            ScriptOffset::AfterTime(secs)
+        } else {
+            ScriptOffset::Time(secs)
+        })
+    } else {
+        let ticks: u64 = value.parse().ok()?;
+        Some(if relative {
+            ScriptOffset::AfterTicks(ticks)
+        } else {
+            ScriptOffset::Tick(ticks)
+        })
+    }
+}
+
+fn parse_action(name: &str) -> Option<InputAction> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+/// Checks the board against the assertions due this tick, and ends the game when the
+/// script says so.
+pub fn check_board(
+    clock: Res<GameClock>,
+    game: Res<Game>,
+    mut checks: ResMut<ScriptChecks>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if checks.run(clock.tick.saturating_sub(1), &game.board) {
+        next_state.set(AppState::Stopped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game_lib::*;
+    use crate::play_box::*;
+    use crate::rules::*;
+
+    const EXAMPLE: &str = include_str!("../assets/scripts/example.txt");
+
+    const LINE_CLEAR: &str = include_str!("../assets/scripts/line_clear.txt");
+
+    #[test]
+    fn parse_reads_seed_offsets_actions_and_expected_rows() {
+        let script = InputScript::parse(
+            "// comment\n\
+             seed 42\n\
+             10 move_left rotate_cw // trailing comment\n\
+             +5 col=3\n\
+             2.5s hard_drop\n\
+             +0.5s expect\n\
+             ..#\n\
+             ###\n\
+             +1 quit\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.seed, Some(42));
+        let offsets: Vec<ScriptOffset> = script.steps.iter().map(|s| s.at).collect();
+        assert_eq!(
+            offsets,
+            vec![
+                ScriptOffset::Tick(10),
+                ScriptOffset::AfterTicks(5),
+                ScriptOffset::Time(2.5),
+                ScriptOffset::AfterTime(0.5),
+                ScriptOffset::AfterTicks(1),
+            ]
+        );
+        assert_eq!(
+            script.steps[0].actions,
+            vec![InputAction::MoveLeft, InputAction::RotateCw]
+        );
+        assert_eq!(script.steps[1].target_col, Some(3));
+        assert_eq!(
+            script.steps[3].expect,
+            Some(vec!["..#".to_string(), "###".to_string()])
+        );
+        assert!(script.steps[4].quit);
+    }
+
+    #[test]
+    fn parse_reports_the_bad_line() {
+        for (text, line) in [
+            ("10 move_left\nsoon hard_drop", "line 2"),
+            ("10 fly", "line 1"),
+            ("10 col=x", "line 1"),
+            ("seed", "line 1"),
+            ("seed 1\nseed 2", "line 2"),
+        ] {
+            match InputScript::parse(text) {
+                Err(MyError::InvalidScript(msg)) => assert!(msg.starts_with(line), "{}", msg),
+                other => panic!("{:?} parsed as {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn json_scripts_carry_a_seed() {
+        let script: InputScript = serde_json::from_str(
+            r#"{"seed": 7, "steps": [{"tick": 10, "actions": ["move_left"]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(script.seed, Some(7));
+        assert_eq!(script.steps[0].at, ScriptOffset::Tick(10));
+    }
+
+    #[test]
+    fn compile_resolves_offsets_and_merges_frames() {
+        let script =
+            InputScript::parse("10 move_left\n+0 rotate_cw\n0.5s expect\n#\n+2 col=4 quit\n")
+                .unwrap();
+        let (replay, checks) = script.compile("hash".to_string(), 3, 64.0);
+
+        assert_eq!(replay.seed, 3);
+        let events: Vec<(u64, ActionFrame)> = replay
+            .events
+            .iter()
+            .map(|e| (e.tick, e.frame.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (
+                    10,
+                    ActionFrame {
+                        actions: vec![InputAction::MoveLeft, InputAction::RotateCw],
+                        target_col: None,
+                    }
+                ),
+                (
+                    34,
+                    ActionFrame {
+                        actions: Vec::new(),
+                        target_col: Some(4),
+                    }
+                ),
+            ]
+        );
+        assert_eq!(checks.checks.len(), 1);
+        assert_eq!(checks.checks[0].tick, 32);
+        assert_eq!(checks.quit_tick, Some(34));
+    }
+
+    #[test]
+    fn verify_compares_occupancy_of_bottom_rows() {
+        let mut board = Board::new(4, 3, 3);
+        board.place(
+            &BoxPos::new(0, 0),
+            &[BoxPos::new(0, 0), BoxPos::new(0, 1)],
+            0,
+        );
+        board.place(&BoxPos::new(1, 1), &[BoxPos::default()], 2);
+        let check = |rows: &[&str]| BoardCheck {
+            tick: 0,
+            rows: rows.iter().map(|r| r.to_string()).collect(),
+        };
+
+        assert_eq!(check(&["##."]).verify(&board), Ok(()));
+        assert_eq!(check(&[".X.", "ab."]).verify(&board), Ok(()));
+        assert_eq!(
+            check(&["...", "##."]).verify(&board),
+            Err(vec![".#.".to_string(), "##.".to_string()])
+        );
+        assert!(check(&["##"]).verify(&board).is_err());
+        assert!(
+            check(&["...", "...", "...", "...", "##."])
+                .verify(&board)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn example_script_is_seeded() {
+        let script = InputScript::parse(EXAMPLE).unwrap();
+        assert_eq!(script.seed, Some(7));
+
+        let (_, checks) = script.compile(String::new(), 7, 64.0);
+        assert_eq!(checks.checks.len(), 3);
+        assert!(checks.quit_tick.is_some());
+    }
+
+    /// Plays `text` through [`GameRules`] the way the game does with `--script`, tick
+    /// by tick until the script quits, and returns the board check results.
+    fn play_script(text: &str) -> ScriptChecks {
+        let game_lib = GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap();
+        let config = &game_lib.config;
+        let script = InputScript::parse(text).unwrap();
+        let seed = script.seed.unwrap();
+        let (replay, mut checks) = script.compile(String::new(), seed, config.tick_rate);
+        let mut player = ReplayPlayer::new(replay);
+        let index_gen = IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            seed,
+        );
+        let mut rules = GameRules::new(&game_lib, index_gen, config.tick_rate);
+
+        for tick in 0.. {
+            rules.tick(&player.frame_at(tick), &game_lib);
+            if checks.run(tick, &rules.board) {
+                break;
+            }
+            assert!(
+                !rules.is_over(),
+                "script did not quit before the game ended"
+            );
+        }
+        checks
+    }
+
+    #[test]
+    fn example_script_passes_its_board_checks() {
+        let checks = play_script(EXAMPLE);
+        assert_eq!((checks.passed, checks.failed), (3, 0));
+    }
+
+    #[test]
+    fn line_clear_script_passes_its_board_checks() {
+        let checks = play_script(LINE_CLEAR);
+        assert_eq!((checks.passed, checks.failed), (2, 0));
+    }
+}
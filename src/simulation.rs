@@ -0,0 +1,94 @@
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::headless::*;
+use crate::input::*;
+use crate::play_box::*;
+use crate::replay::*;
+use crate::rules::*;
+
+/// A game played by [`GameRules`] alone, without entities or `Time`, which only stops
+/// where a player has something to decide. Every call to [`Simulation::apply`] or
+/// [`Simulation::place`] plays the tick of the decision, then the ticks the game spends
+/// without taking input: spawning, soft dropping, clearing and collapsing rows.
+pub struct Simulation<'a> {
+    game_lib: &'a GameLib,
+    pub rules: GameRules,
+}
+
+impl<'a> Simulation<'a> {
+    /// Starts a game drawing pieces the same way as the windowed game with `seed`.
+    pub fn new(game_lib: &'a GameLib, seed: u64) -> Self {
+        let config = &game_lib.config;
+        let index_gen = IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            seed,
+        );
+
+        let mut simulation = Self {
+            game_lib,
+            rules: GameRules::new(game_lib, index_gen, config.tick_rate),
+        };
+        simulation.advance();
+        simulation
+    }
+
+    #[inline]
+    pub fn is_over(&self) -> bool {
+        self.rules.is_over()
+    }
+
+    pub fn result(&self) -> GameResult {
+        let scoreboard = &self.rules.scoreboard;
+        GameResult {
+            seed: self.rules.index_gen().seed(),
+            score: scoreboard.score,
+            lines: scoreboard.lines,
+            level: scoreboard.level,
+            pieces: self.rules.pieces(),
+            ticks: self.rules.ticks(),
+            elapsed: (self.rules.ticks() as f64 / self.game_lib.config.tick_rate) as f32,
+            failed_checks: None,
+        }
+    }
+
+    /// Placements the falling piece can reach from where it is now.
+    pub fn placements(&self) -> Vec<Placement> {
+        match self.rules.piece() {
+            Some((index, pos)) => {
+                reachable_placements(&self.rules.board, index, pos, self.game_lib)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Applies a player action and returns the points scored. Actions that are blocked,
+    /// as well as pause and save, only let the tick pass.
+    pub fn apply(&mut self, action: InputAction) -> u32 {
+        let score = self.rules.scoreboard.score;
+        let frame = ActionFrame {
+            actions: vec![action],
+            target_col: None,
+        };
+        self.rules.tick(&frame, self.game_lib);
+        self.advance();
+        self.rules.scoreboard.score - score
+    }
+
+    /// Locks the falling piece at `placement` in a single tick and returns the points
+    /// scored.
+    pub fn place(&mut self, placement: &Placement) -> u32 {
+        let score = self.rules.scoreboard.score;
+        self.rules.place(placement, self.game_lib);
+        self.advance();
+        self.rules.scoreboard.score - score
+    }
+
+    /// Plays ticks without input until the piece takes input again or the game ends.
+    fn advance(&mut self) {
+        let idle = ActionFrame::default();
+        while !matches!(self.rules.phase(), Phase::Falling | Phase::Over) {
+            self.rules.tick(&idle, self.game_lib);
+        }
+    }
+}
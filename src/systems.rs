@@ -1,9 +1,22 @@
+use crate::accessibility::*;
+use crate::board::*;
+use crate::bot::*;
 use crate::game_lib::*;
 use crate::game_panel::*;
+use crate::high_score::*;
+use crate::hint::*;
+use crate::input::*;
+use crate::line_clear::*;
+use crate::messages::*;
 use crate::play_box::*;
 use crate::preview::*;
+use crate::replay::*;
+use crate::rules::*;
+use crate::save::*;
+use crate::script::*;
+use crate::theme::*;
 use crate::utils::*;
-use bevy::prelude::*;
+use bevy::{prelude::*, time::TimeUpdateStrategy};
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum AppState {
@@ -12,10 +25,126 @@ pub enum AppState {
     InitBox,
     Playing,
     FastDown,
-    Flashing,
+    ClearingRows,
+    Collapsing,
     Stopped,
 }
 
+impl From<Phase> for AppState {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Spawning => AppState::InitBox,
+            Phase::Falling => AppState::Playing,
+            Phase::FastDown => AppState::FastDown,
+            Phase::Clearing => AppState::ClearingRows,
+            Phase::Collapsing => AppState::Collapsing,
+            Phase::Over => AppState::Stopped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+/// The single-player game. Its rules decide everything; the entities only follow them.
+#[derive(Resource, Debug, Deref, DerefMut)]
+pub struct Game(pub GameRules);
+
+/// Events of the last tick of the rules, until the piece entities have followed them.
+#[derive(Resource, Debug, Default)]
+pub struct TickEvents(pub Vec<RuleEvent>);
+
+/// The entities showing the pieces of a game: the falling one on the panel, the next
+/// one and the held one in their previews.
+pub struct PieceView<'a> {
+    pub game_panel: &'a mut GamePanel,
+    pub play_box: &'a mut PlayBox,
+    pub preview: &'a mut Preview,
+    pub hold: &'a mut Preview,
+}
+
+impl PieceView<'_> {
+    /// Replaces whatever is shown by the pieces of `rules`.
+    pub fn reset(&mut self, rules: &GameRules, commands: &mut Commands, game_lib: &GameLib) {
+        self.play_box.despawn(commands);
+        if let Some((index, pos)) = rules.piece() {
+            self.play_box.init(
+                index.clone(),
+                pos.clone(),
+                &self.game_panel.play_region,
+                game_lib,
+                commands,
+            );
+        }
+
+        self.preview.show(rules.next(), commands, game_lib);
+        match rules.hold() {
+            Some(index) => self.hold.show(index, commands, game_lib),
+            None => self.hold.play_box.despawn(commands),
+        }
+    }
+
+    /// Follows the events of a tick of `rules`.
+    pub fn update(
+        &mut self,
+        events: &[RuleEvent],
+        rules: &GameRules,
+        commands: &mut Commands,
+        game_lib: &GameLib,
+    ) {
+        for event in events {
+            let region = &self.game_panel.play_region;
+            match event {
+                RuleEvent::Spawned(pos) => {
+                    self.play_box.transfer(
+                        &mut self.preview.play_box,
+                        pos.clone(),
+                        region,
+                        commands,
+                        game_lib,
+                    );
+                    self.preview.show(rules.next(), commands, game_lib);
+                }
+                RuleEvent::Moved(pos) | RuleEvent::Dropped(pos) => {
+                    self.play_box
+                        .move_to(pos.clone(), region, game_lib, commands);
+                }
+                RuleEvent::Rotated(rotation) => {
+                    self.play_box.rotate(*rotation, region, game_lib, commands);
+                }
+                RuleEvent::Held(pos) => {
+                    let mut swapped = PlayBox::default();
+                    if let Some(pos) = pos {
+                        swapped.transfer(
+                            &mut self.hold.play_box,
+                            pos.clone(),
+                            region,
+                            commands,
+                            game_lib,
+                        );
+                    }
+                    self.hold.play_box.transfer(
+                        self.play_box,
+                        BoxPos::new(0, 0),
+                        &self.hold.region,
+                        commands,
+                        game_lib,
+                    );
+                    *self.play_box = swapped;
+                }
+                RuleEvent::Locked => {
+                    self.game_panel.put_down_play_box(self.play_box, game_lib);
+                }
+                RuleEvent::LinesCleared(_) | RuleEvent::LevelUp(_) => {}
+            }
+        }
+    }
+}
+
 pub fn setup_game(
     mut next_state: ResMut<NextState<AppState>>,
     mut commands: Commands,
@@ -23,12 +152,14 @@ pub fn setup_game(
     mut exit_app: MessageWriter<AppExit>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    mut window: Single<&mut Window>,
+    window: Option<Single<&mut Window>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
 ) {
     let game_lib = match GameLib::new(
         args.config_path.as_path(),
         meshes.as_mut(),
         materials.as_mut(),
+        args.headless || args.tui,
     ) {
         Ok(lib) => lib,
         Err(err) => {
@@ -38,300 +169,312 @@ pub fn setup_game(
         }
     };
 
-    let config = &game_lib.config;
+    let theme_manager = match ThemeManager::new(&game_lib.config.theme_config) {
+        Ok(manager) => manager,
+        Err(err) => {
+            error!("Failed to initialize ThemeManager {}", err);
+            exit_app.write(AppExit::error());
+            return;
+        }
+    };
 
-    let window_size = &config.window_size;
-    window
-        .resolution
-        .set(window_size.width as f32, window_size.height as f32);
+    let high_scores = match HighScores::load(high_score_path(&game_lib)) {
+        Ok(high_scores) => high_scores,
+        Err(err) => {
+            error!(
+                "Failed to load high scores, starting a fresh table: {}",
+                err
+            );
+            HighScores::recover(high_score_path(&game_lib))
+        }
+    };
 
-    commands.spawn(Camera2d);
+    let config = &game_lib.config;
 
-    let cmd = &mut commands;
-    let game_panel = GamePanel::new(cmd, &game_lib, meshes.as_mut(), materials.as_mut());
-    let preview = Preview::new(cmd, &game_lib, meshes.as_mut(), materials.as_mut());
-    let box_config = &config.box_config;
-
-    commands.insert_resource(IndexGen::new(
-        box_config.play_box_type_count(),
-        PLAY_BOX_ROTATE_COUNT,
-    ));
-    commands.insert_resource(DropDownTimer(repeat_timer(config.drop_down_interval)));
-    commands.insert_resource(FastDownTimer(CountDownTimer::new(
-        config.fast_down_interval,
-        config.fast_down_max_steps,
-    )));
-    commands.insert_resource(FlashFullLineTimer(CountDownTimer::new(
-        config.flash_full_line_interval,
-        config.flash_full_line_max_count,
-    )));
-    commands.insert_resource(game_lib);
-    commands.insert_resource(game_panel);
-    commands.insert_resource(PlayBox::default());
-    commands.insert_resource(preview);
+    let bindings_path = args.bindings_path.as_ref().unwrap_or(&config.bindings_path);
+    let bindings = match InputBindings::load(bindings_path) {
+        Ok(bindings) => bindings,
+        Err(err) => {
+            error!("Failed to load input bindings {}", err);
+            exit_app.write(AppExit::error());
+            return;
+        }
+    };
 
-    next_state.set(AppState::InitBox);
+    let replay = match &args.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => Some(replay),
+            Err(err) => {
+                error!("Failed to load replay {}", err);
+                exit_app.write(AppExit::error());
+                return;
+            }
+        },
+        None => None,
+    };
 
-    info!("Finished setting up game");
-}
+    let mut bot_config = config.bot_config.clone();
+    if let Some(path) = &args.weights {
+        match BotWeights::load(path) {
+            Ok(weights) => bot_config.weights = weights,
+            Err(err) => {
+                error!("Failed to load bot weights {}", err);
+                exit_app.write(AppExit::error());
+                return;
+            }
+        }
+    }
 
-pub fn reset_play_box(
-    mut next_state: ResMut<NextState<AppState>>,
-    mut commands: Commands,
-    game_lib: Res<GameLib>,
-    game_panel: Res<GamePanel>,
-    mut play_box: ResMut<PlayBox>,
-    mut index_gen: ResMut<IndexGen>,
-    mut preview: ResMut<Preview>,
-    mut drop_down_timer: ResMut<DropDownTimer>,
-) {
-    if play_box.is_valid() {
-        return;
+    if let Some(mut window) = window {
+        let window_size = &config.window_size;
+        window
+            .resolution
+            .set(window_size.width as f32, window_size.height as f32);
+
+        commands.spawn(Camera2d);
     }
 
-    if let Some(index) = preview.play_box.index() {
-        if let Some(new_pos) = game_panel.init_pos(index, game_lib.as_ref()) {
-            play_box.transfer(
-                &mut preview.play_box,
-                new_pos,
-                &game_panel.play_region,
-                &mut commands,
-                game_lib.as_ref(),
-            );
-            preview.reset_box(index_gen.as_mut(), &mut commands, game_lib.as_ref());
-        } else {
-            next_state.set(AppState::Stopped);
-            return;
+    let cmd = &mut commands;
+    let mut game_panel = GamePanel::new(cmd, &game_lib, meshes.as_mut(), materials.as_mut());
+    let mut preview = Preview::new(
+        PreviewKind::Next,
+        cmd,
+        &game_lib,
+        meshes.as_mut(),
+        materials.as_mut(),
+    );
+    let mut hold_slot = HoldSlot::new(cmd, &game_lib, meshes.as_mut(), materials.as_mut());
+    let mut play_box = PlayBox::default();
+    let mut index_gen = match args.seed.or(config.seed) {
+        Some(seed) => IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            seed,
+        ),
+        None => IndexGen::new(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+        ),
+    };
+    let mut save = None;
+    let hash = match config_hash(&args.config_path) {
+        Ok(hash) => hash,
+        Err(err) => {
+            error!("Failed to hash config file {}", err);
+            String::new()
         }
-    } else {
-        let index = index_gen.rand_box();
-        if let Some(new_pos) = game_panel.init_pos(&index, game_lib.as_ref()) {
-            play_box.init(
-                index,
-                new_pos,
-                &game_panel.play_region,
-                game_lib.as_ref(),
-                &mut commands,
-            );
-            preview.reset_box(index_gen.as_mut(), &mut commands, game_lib.as_ref());
-        } else {
-            next_state.set(AppState::Stopped);
+    };
+
+    let mut tick_rate = config.tick_rate;
+    if let Some(replay) = replay {
+        if replay.config_hash != hash {
+            warn!("Replay was recorded with a different config, playback may diverge");
+        }
+        index_gen = IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            replay.seed,
+        );
+        tick_rate = replay.tick_rate;
+        commands.insert_resource(ReplayPlayer::new(replay));
+    } else if let Some(path) = &args.script {
+        let script = match InputScript::load(path) {
+            Ok(script) => script,
+            Err(err) => {
+                error!("Failed to load input script {}", err);
+                exit_app.write(AppExit::error());
+                return;
+            }
+        };
+        // The board checks only hold for the pieces the script was written for.
+        let Some(seed) = args.seed.or(script.seed).or(config.seed) else {
+            error!("Input script has no seed, add one to it or pass --seed");
+            exit_app.write(AppExit::error());
             return;
+        };
+        index_gen = IndexGen::with_seed(
+            config.box_config.play_box_type_count(),
+            PLAY_BOX_ROTATE_COUNT,
+            seed,
+        );
+        let (replay, checks) = script.compile(hash, index_gen.seed(), config.tick_rate);
+        commands.insert_resource(ReplayPlayer::new(replay));
+        commands.insert_resource(checks);
+    } else if args.resume {
+        info!("Resumed games are not recorded");
+        match SaveGame::load(save_path(&game_lib), &game_lib) {
+            Ok(loaded) => save = Some(loaded),
+            Err(err) => error!("Failed to resume saved game, starting a new one: {}", err),
         }
+    } else {
+        commands.insert_resource(ReplayRecorder {
+            replay: Replay::new(hash, index_gen.seed(), config.tick_rate),
+        });
     }
+    fixed_time.set_timestep_hz(tick_rate);
 
-    drop_down_timer.0.unpause();
-    next_state.set(AppState::Playing);
-}
-
-pub fn process_input(
-    mut next_state: ResMut<NextState<AppState>>,
-    mut commands: Commands,
-    game_lib: Res<GameLib>,
-    game_panel: Res<GamePanel>,
-    mut play_box: ResMut<PlayBox>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut fast_down_timer: ResMut<FastDownTimer>,
-) {
-    if keys.just_pressed(KeyCode::ArrowLeft) {
-        try_move_left(
-            play_box.as_mut(),
-            &mut commands,
-            game_lib.as_ref(),
-            game_panel.as_ref(),
-        );
-    } else if keys.just_pressed(KeyCode::ArrowRight) {
-        try_move_right(
-            play_box.as_mut(),
-            &mut commands,
-            game_lib.as_ref(),
-            game_panel.as_ref(),
-        );
-    } else if keys.just_pressed(KeyCode::ArrowUp) {
-        try_rotate(
-            play_box.as_mut(),
-            &mut commands,
-            game_lib.as_ref(),
-            game_panel.as_ref(),
-        );
-    } else if keys.just_pressed(KeyCode::ArrowDown) {
-        start_fast_down(
-            next_state.as_mut(),
-            play_box.as_mut(),
-            game_panel.as_ref(),
-            game_lib.as_ref(),
-            fast_down_timer.as_mut(),
-        );
+    let mut rules = GameRules::new(&game_lib, index_gen, tick_rate);
+    if let Some(save) = save {
+        save.restore(&mut rules, &game_lib);
     }
-}
+    PieceView {
+        game_panel: &mut game_panel,
+        play_box: &mut play_box,
+        preview: &mut preview,
+        hold: &mut hold_slot.preview,
+    }
+    .reset(&rules, &mut commands, &game_lib);
 
-pub fn drop_down_play_box(
-    mut next_state: ResMut<NextState<AppState>>,
-    mut commands: Commands,
-    game_lib: Res<GameLib>,
-    mut game_panel: ResMut<GamePanel>,
-    mut play_box: ResMut<PlayBox>,
-    time: Res<Time>,
-    mut drop_down_timer: ResMut<DropDownTimer>,
-    mut flash_full_line_timer: ResMut<FlashFullLineTimer>,
-) {
-    if !play_box.is_valid() {
-        return;
+    if args.headless {
+        // Advance time by exactly one tick per update so that the game runs as fast
+        // as the machine allows.
+        commands.insert_resource(TimeUpdateStrategy::ManualDuration(fixed_time.timestep()));
     }
 
-    drop_down_timer.0.tick(time.delta());
-    if drop_down_timer.0.is_finished() {
-        let index = play_box.index().unwrap();
-        let new_pos = BoxPos::new(play_box.pos().row - 1, play_box.pos().col);
-
-        if game_panel.can_move_to(&new_pos, index, game_lib.as_ref()) {
-            play_box.move_to(
-                new_pos,
-                &game_panel.play_region,
-                game_lib.as_ref(),
-                &mut commands,
-            );
-        } else {
-            game_panel.put_down_play_box(play_box.as_mut(), game_lib.as_ref());
-            drop_down_timer.0.pause();
-
-            if game_panel.has_full_lines() {
-                next_state.set(AppState::Flashing);
-                flash_full_line_timer.0.start();
-            } else if game_panel.reach_top() {
-                next_state.set(AppState::Stopped);
-            } else {
-                next_state.set(AppState::InitBox);
-            }
-        }
+    if args.bot {
+        info!("Bot is playing");
+        commands.insert_resource(Bot::new(&bot_config));
     }
+
+    info!("Using piece seed {}", rules.index_gen().seed());
+    next_state.set(rules.phase().into());
+    commands.insert_resource(Game(rules));
+    commands.insert_resource(LineClearer::new(&config.line_clear_config));
+    commands.insert_resource(Hint::new(&config.hint_config));
+    commands.insert_resource(config.motion_config.clone());
+    commands.insert_resource(config.audio_config.clone());
+    commands.insert_resource(config.music_config.clone());
+    commands.insert_resource(AccessibilitySettings::new(&config.accessibility_config));
+    commands.insert_resource(high_scores);
+    commands.insert_resource(game_lib);
+    commands.insert_resource(game_panel);
+    commands.insert_resource(play_box);
+    commands.insert_resource(preview);
+    commands.insert_resource(hold_slot);
+    commands.insert_resource(theme_manager);
+    commands.insert_resource(bindings);
+    commands.insert_resource(ActionState::default());
+
+    info!("Finished setting up game");
 }
 
-pub fn fast_move_down(
+/// Plays one tick of the rules with the actions collected for it, and moves the app to
+/// the state matching the phase the rules ended in.
+pub fn play_tick(
     mut next_state: ResMut<NextState<AppState>>,
-    mut commands: Commands,
+    app_state: Res<State<AppState>>,
     game_lib: Res<GameLib>,
-    game_panel: Res<GamePanel>,
-    mut play_box: ResMut<PlayBox>,
-    mut fast_down_timer: ResMut<FastDownTimer>,
-    time: Res<Time>,
+    mut game: ResMut<Game>,
+    actions: Res<TickActions>,
+    mut tick_events: ResMut<TickEvents>,
+    mut gameplay_messages: MessageWriter<GameplayMessage>,
 ) {
-    if !play_box.is_valid() {
-        fast_down_timer.0.stop();
-        return;
-    }
+    tick_events.0 = game.tick(&actions.0, game_lib.as_ref());
+    gameplay_messages.write_batch(tick_events.0.iter().filter_map(gameplay_message));
 
-    let mut stop = false;
-    if fast_down_timer.0.update(time.as_ref()) {
-        let new_pos = BoxPos::new(play_box.pos().row - 1, play_box.pos().col);
-        let index = play_box.index().unwrap();
-
-        if game_panel.can_move_to(&new_pos, index, game_lib.as_ref()) {
-            play_box.move_to(
-                new_pos,
-                &game_panel.play_region,
-                game_lib.as_ref(),
-                &mut commands,
-            );
-        } else {
-            stop = true;
-        }
+    let state = AppState::from(game.phase());
+    if *app_state.get() != state {
+        next_state.set(state);
     }
+}
 
-    if stop || fast_down_timer.0.is_finished() {
-        next_state.set(AppState::Playing);
-        fast_down_timer.0.stop();
+/// The message played for a rule event, if any.
+pub fn gameplay_message(event: &RuleEvent) -> Option<GameplayMessage> {
+    match event {
+        RuleEvent::Moved(_) => Some(GameplayMessage::Move),
+        RuleEvent::Rotated(_) => Some(GameplayMessage::Rotate),
+        RuleEvent::Held(_) => Some(GameplayMessage::Hold),
+        RuleEvent::Locked => Some(GameplayMessage::Lock),
+        RuleEvent::LinesCleared(count) => Some(GameplayMessage::LineClear(*count)),
+        RuleEvent::LevelUp(level) => Some(GameplayMessage::LevelUp(*level)),
+        RuleEvent::Spawned(_) | RuleEvent::Dropped(_) => None,
     }
 }
 
-pub fn flash_full_rows(
+/// Moves the piece entities along with the events of the tick.
+pub fn sync_pieces(
     mut commands: Commands,
-    mut next_state: ResMut<NextState<AppState>>,
-    mut game_panel: ResMut<GamePanel>,
     game_lib: Res<GameLib>,
-    mut flash_full_line_timer: ResMut<FlashFullLineTimer>,
-    time: Res<Time>,
+    game: Res<Game>,
+    mut tick_events: ResMut<TickEvents>,
+    mut game_panel: ResMut<GamePanel>,
+    mut play_box: ResMut<PlayBox>,
+    mut preview: ResMut<Preview>,
+    mut hold_slot: ResMut<HoldSlot>,
 ) {
-    if flash_full_line_timer.0.update(time.as_ref()) {
-        game_panel.toggle_full_rows_visibility(&mut commands);
-    }
-
-    if flash_full_line_timer.0.is_finished() {
-        game_panel.remove_full_rows(&mut commands, game_lib.as_ref());
-        flash_full_line_timer.0.stop();
-        next_state.set(AppState::InitBox);
+    let events = std::mem::take(&mut tick_events.0);
+    PieceView {
+        game_panel: game_panel.as_mut(),
+        play_box: play_box.as_mut(),
+        preview: preview.as_mut(),
+        hold: &mut hold_slot.preview,
     }
+    .update(&events, &game, &mut commands, game_lib.as_ref());
 }
 
-fn try_move_left(
-    play_box: &mut PlayBox,
-    commands: &mut Commands,
-    game_lib: &GameLib,
-    game_panel: &GamePanel,
+#[derive(Message, Debug, Clone)]
+pub struct RestartGame;
+
+/// Throws away the current game and starts over with the same piece sequence.
+pub fn restart_game(
+    mut restart: MessageReader<RestartGame>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    game_lib: Res<GameLib>,
+    mut game_panel: ResMut<GamePanel>,
+    mut play_box: ResMut<PlayBox>,
+    mut preview: ResMut<Preview>,
+    mut hold_slot: ResMut<HoldSlot>,
+    mut game: ResMut<Game>,
+    mut line_clearer: ResMut<LineClearer>,
+    mut clock: ResMut<GameClock>,
 ) {
-    if !play_box.is_valid() {
+    if restart.read().count() == 0 {
         return;
     }
 
-    let index = play_box.index().unwrap();
-    let new_pos = BoxPos::new(play_box.pos().row, play_box.pos().col - 1);
-
-    if game_panel.can_move_to(&new_pos, index, game_lib) {
-        play_box.move_to(new_pos, &game_panel.play_region, game_lib, commands);
+    game.restart(game_lib.as_ref());
+    PieceView {
+        game_panel: game_panel.as_mut(),
+        play_box: play_box.as_mut(),
+        preview: preview.as_mut(),
+        hold: &mut hold_slot.preview,
     }
+    .reset(&game, &mut commands, game_lib.as_ref());
+    *line_clearer = LineClearer::new(&game_lib.config.line_clear_config);
+    clock.tick = 0;
+
+    next_state.set(game.phase().into());
+    info!("Game restarted");
 }
 
-fn try_move_right(
-    play_box: &mut PlayBox,
-    commands: &mut Commands,
-    game_lib: &GameLib,
-    game_panel: &GamePanel,
+pub fn toggle_pause(
+    actions: Res<ActionState>,
+    app_state: Res<State<AppState>>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
 ) {
-    if !play_box.is_valid() {
+    if !actions.just_pressed(InputAction::Pause) {
         return;
     }
 
-    let index = play_box.index().unwrap();
-    let new_pos = BoxPos::new(play_box.pos().row, play_box.pos().col + 1);
-
-    if game_panel.can_move_to(&new_pos, index, game_lib) {
-        play_box.move_to(new_pos, &game_panel.play_region, game_lib, commands);
-    }
-}
-
-fn try_rotate(
-    play_box: &mut PlayBox,
-    commands: &mut Commands,
-    game_lib: &GameLib,
-    game_panel: &GamePanel,
-) {
-    if !play_box.is_valid() {
+    if matches!(app_state.get(), AppState::Loading | AppState::Stopped) {
         return;
     }
 
-    let mut new_index = play_box.index().unwrap().clone();
-    new_index.rotate();
-
-    if game_panel.can_move_to(play_box.pos(), &new_index, game_lib) {
-        play_box.rotate(&game_panel.play_region, game_lib, commands);
+    match pause_state.get() {
+        PauseState::Running => {
+            info!("Game paused");
+            next_pause_state.set(PauseState::Paused);
+        }
+        PauseState::Paused => {
+            info!("Game resumed");
+            next_pause_state.set(PauseState::Running);
+        }
     }
 }
 
-fn start_fast_down(
-    next_state: &mut NextState<AppState>,
-    play_box: &PlayBox,
-    game_panel: &GamePanel,
-    game_lib: &GameLib,
-    fast_down_timer: &mut FastDownTimer,
-) {
-    if !play_box.is_valid() {
-        return;
-    }
-
-    let index = play_box.index().unwrap();
-    let new_pos = BoxPos::new(play_box.pos().row - 1, play_box.pos().col);
-    if game_panel.can_move_to(&new_pos, index, game_lib) {
-        next_state.set(AppState::FastDown);
-        fast_down_timer.0.start();
-    }
+pub fn announce_game_over(mut gameplay_messages: MessageWriter<GameplayMessage>) {
+    info!("Game over");
+    gameplay_messages.write(GameplayMessage::GameOver);
 }
@@ -0,0 +1,261 @@
+use crate::accessibility::*;
+use crate::game_lib::*;
+use crate::my_error::MyError;
+use crate::utils::*;
+use bevy::{math::Affine2, prelude::*};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct Theme {
+    #[serde(skip)]
+    pub name: String,
+    /// Block styles indexed by play box type with garbage last. Types without an entry
+    /// keep their configured color.
+    #[serde(default)]
+    pub pieces: Vec<PieceStyle>,
+    #[serde(default)]
+    pub game_panel: PanelStyle,
+    #[serde(default)]
+    pub preview: PanelStyle,
+    #[serde(default)]
+    pub hold: PanelStyle,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PieceStyle {
+    pub color: Option<[u8; 4]>,
+    pub texture: Option<String>,
+    pub atlas_cell: Option<AtlasCell>,
+}
+
+/// A cell of a sprite atlas laid out as a grid of `columns` x `rows` equally sized
+/// cells, counted row by row from the top-left corner.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AtlasCell {
+    pub columns: u32,
+    pub rows: u32,
+    pub index: u32,
+}
+
+impl AtlasCell {
+    pub fn uv_transform(&self) -> Affine2 {
+        let columns = self.columns.max(1);
+        let rows = self.rows.max(1);
+        let scale = Vec2::new(1.0 / columns as f32, 1.0 / rows as f32);
+        let offset = Vec2::new((self.index % columns) as f32, (self.index / columns) as f32);
+        Affine2::from_scale_angle_translation(scale, 0.0, offset * scale)
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PanelStyle {
+    pub background_color: Option<[u8; 4]>,
+    pub background_image: Option<String>,
+    pub border_color: Option<[u8; 4]>,
+    pub border_image: Option<String>,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelPart {
+    GamePanelBackground,
+    GamePanelBorder,
+    PreviewBackground,
+    PreviewBorder,
+    HoldBackground,
+    HoldBorder,
+}
+
+#[derive(Message, Debug, Clone)]
+pub struct SelectTheme(pub String);
+
+#[derive(Resource, Debug)]
+pub struct ThemeManager {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl ThemeManager {
+    pub fn new(config: &ThemeConfig) -> Result<Self, MyError> {
+        let mut themes: Vec<Theme> = Vec::new();
+        for name in config.themes.iter() {
+            themes.push(Self::load_theme(&config.dir, name)?);
+        }
+
+        let mut manager = Self { themes, current: 0 };
+        manager.select(&config.current)?;
+
+        info!(
+            "ThemeManager initialized with {} themes",
+            manager.themes.len()
+        );
+
+        Ok(manager)
+    }
+
+    #[inline]
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<(), MyError> {
+        let Some(index) = self.themes.iter().position(|t| t.name == name) else {
+            return Err(MyError::UnknownTheme(name.to_string()));
+        };
+
+        self.current = index;
+        info!("Selected theme {}", name);
+
+        Ok(())
+    }
+
+    pub fn select_next(&mut self) {
+        self.current = (self.current + 1) % self.themes.len();
+        info!("Selected theme {}", self.current().name);
+    }
+
+    fn load_theme<P: AsRef<Path>>(dir: P, name: &str) -> Result<Theme, MyError> {
+        let path = dir.as_ref().join(format!("{}.json", name));
+        let mut theme: Theme = read_json(path)?;
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+}
+
+impl Theme {
+    pub fn piece_material(
+        &self,
+        type_index: usize,
+        game_lib: &GameLib,
+        asset_server: &AssetServer,
+    ) -> ColorMaterial {
+        let default_color = game_lib.config.box_color(type_index);
+        let Some(style) = self.pieces.get(type_index) else {
+            return ColorMaterial::from_color(default_color);
+        };
+
+        let texture = style
+            .texture
+            .as_ref()
+            .map(|path| asset_server.load::<Image>(path));
+        let color = match (&style.color, &texture) {
+            (Some(color), _) => vec_to_color(color),
+            (None, Some(_)) => Color::WHITE,
+            (None, None) => default_color,
+        };
+        let uv_transform = style
+            .atlas_cell
+            .as_ref()
+            .map(|cell| cell.uv_transform())
+            .unwrap_or_default();
+
+        ColorMaterial {
+            color,
+            uv_transform,
+            texture,
+            ..default()
+        }
+    }
+
+    pub fn panel_material(
+        &self,
+        part: PanelPart,
+        game_lib: &GameLib,
+        asset_server: &AssetServer,
+    ) -> ColorMaterial {
+        let panel_config = &game_lib.config.game_panel_config;
+        let preview_config = &game_lib.config.preview_config;
+        let hold_config = &game_lib.config.hold_config;
+        let (color, image, default_color) = match part {
+            PanelPart::GamePanelBackground => (
+                &self.game_panel.background_color,
+                &self.game_panel.background_image,
+                &panel_config.background_color,
+            ),
+            PanelPart::GamePanelBorder => (
+                &self.game_panel.border_color,
+                &self.game_panel.border_image,
+                &panel_config.border_color,
+            ),
+            PanelPart::PreviewBackground => (
+                &self.preview.background_color,
+                &self.preview.background_image,
+                &preview_config.background_color,
+            ),
+            PanelPart::PreviewBorder => (
+                &self.preview.border_color,
+                &self.preview.border_image,
+                &preview_config.border_color,
+            ),
+            PanelPart::HoldBackground => (
+                &self.hold.background_color,
+                &self.hold.background_image,
+                &hold_config.background_color,
+            ),
+            PanelPart::HoldBorder => (
+                &self.hold.border_color,
+                &self.hold.border_image,
+                &hold_config.border_color,
+            ),
+        };
+
+        let texture = image.as_ref().map(|path| asset_server.load::<Image>(path));
+        let color = match (color, &texture) {
+            (Some(color), _) => vec_to_color(color),
+            (None, Some(_)) => Color::WHITE,
+            (None, None) => vec_to_color(default_color),
+        };
+
+        ColorMaterial {
+            color,
+            texture,
+            ..default()
+        }
+    }
+}
+
+pub fn switch_theme(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut select_theme: MessageReader<SelectTheme>,
+    mut theme_manager: ResMut<ThemeManager>,
+) {
+    for SelectTheme(name) in select_theme.read() {
+        if let Err(err) = theme_manager.select(name) {
+            error!("Failed to select theme: {}", err);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::F2) {
+        theme_manager.select_next();
+    }
+}
+
+pub fn apply_theme(
+    theme_manager: Res<ThemeManager>,
+    accessibility: Res<AccessibilitySettings>,
+    game_lib: Res<GameLib>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    panel_parts: Query<(&PanelPart, &MeshMaterial2d<ColorMaterial>)>,
+) {
+    let theme = theme_manager.current();
+
+    for (type_index, handle) in game_lib.box_colors.iter().enumerate() {
+        if let Some(material) = materials.get_mut(handle) {
+            *material = theme.piece_material(type_index, game_lib.as_ref(), asset_server.as_ref());
+            if let Some(color) =
+                accessibility.piece_color(type_index, &game_lib.config.accessibility_config)
+            {
+                material.color = color;
+            }
+        }
+    }
+
+    for (part, handle) in panel_parts.iter() {
+        if let Some(material) = materials.get_mut(&handle.0) {
+            *material = theme.panel_material(*part, game_lib.as_ref(), asset_server.as_ref());
+        }
+    }
+
+    info!("Applied theme {}", theme.name);
+}
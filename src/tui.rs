@@ -0,0 +1,452 @@
+use crate::board::*;
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::input::*;
+use crate::my_error::MyError;
+use crate::play_box::*;
+use crate::replay::*;
+use crate::save::*;
+use crate::systems::*;
+use bevy::prelude::*;
+use std::io::{self, Write};
+
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+];
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// Frames per second the terminal frontend runs at.
+pub const TUI_FRAME_RATE: f64 = 60.0;
+
+/// Ctrl-C, which raw mode delivers as a plain byte instead of a signal.
+const INTERRUPT: u8 = 3;
+const ESCAPE: u8 = 0x1b;
+
+/// Runs the game in the terminal it was started from: keys are read from stdin and fed
+/// into [`ButtonInput<KeyCode>`], so the regular input bindings apply, and the board is
+/// drawn with colored characters.
+pub struct TuiPlugin;
+
+impl Plugin for TuiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .add_systems(Startup, setup_terminal)
+            .add_systems(
+                PreUpdate,
+                (
+                    read_terminal.run_if(resource_exists::<TerminalScreen>),
+                    read_input.run_if(resource_exists::<InputBindings>),
+                    queue_actions.run_if(
+                        resource_exists::<ActionState>
+                            .and(not(resource_exists::<ReplayPlayer>))
+                            .and(in_state(PauseState::Running)),
+                    ),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    control_playback.run_if(resource_exists::<ReplayPlayer>),
+                    restart_game.run_if(resource_exists::<GamePanel>),
+                    toggle_pause.run_if(resource_exists::<ActionState>),
+                    save_game.run_if(
+                        in_state(AppState::Playing).and(not(resource_exists::<ReplayPlayer>)),
+                    ),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                draw_terminal
+                    .run_if(resource_exists::<TerminalScreen>.and(resource_exists::<GamePanel>)),
+            );
+    }
+}
+
+/// Puts the terminal into raw mode on an alternate screen for as long as it lives.
+pub struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    pub fn enable() -> Result<Self, MyError> {
+        // SAFETY: termios is plain old data and both calls only access the struct
+        // passed to them.
+        let original = unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            // Make reads return right away with whatever input is pending.
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            original
+        };
+
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+
+        Ok(Self { original })
+    }
+
+    /// Returns the bytes typed since the last call without blocking.
+    pub fn read(&self) -> Vec<u8> {
+        let mut result: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            // SAFETY: reads at most `buf.len()` bytes into `buf`.
+            let n = unsafe {
+                libc::read(
+                    libc::STDIN_FILENO,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            result.extend_from_slice(&buf[..n as usize]);
+        }
+        result
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // SAFETY: restores the settings read in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct TerminalScreen {
+    terminal: RawTerminal,
+    /// Keys fed into [`ButtonInput`] last frame, released at the start of the next one
+    /// since terminals do not report key releases.
+    pressed: Vec<KeyCode>,
+    last_frame: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalKey {
+    Key(KeyCode),
+    Interrupt,
+}
+
+/// Decodes the keys in a chunk of terminal input, skipping unknown sequences.
+pub fn parse_terminal_keys(bytes: &[u8]) -> Vec<TerminalKey> {
+    let mut keys: Vec<TerminalKey> = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        i += 1;
+
+        let code = match byte {
+            INTERRUPT => {
+                keys.push(TerminalKey::Interrupt);
+                continue;
+            }
+            ESCAPE if i < bytes.len() && (bytes[i] == b'[' || bytes[i] == b'O') => {
+                let start = i + 1;
+                let end = bytes[start..]
+                    .iter()
+                    .position(|b| b.is_ascii_alphabetic() || *b == b'~')
+                    .map(|p| start + p)
+                    .unwrap_or(bytes.len() - 1);
+                i = end + 1;
+                parse_escape_sequence(&bytes[start..=end.min(bytes.len() - 1)])
+            }
+            ESCAPE => Some(KeyCode::Escape),
+            b'a'..=b'z' => Some(LETTER_KEYS[(byte - b'a') as usize]),
+            b'A'..=b'Z' => Some(LETTER_KEYS[(byte - b'A') as usize]),
+            b'0'..=b'9' => Some(DIGIT_KEYS[(byte - b'0') as usize]),
+            b' ' => Some(KeyCode::Space),
+            b'\r' | b'\n' => Some(KeyCode::Enter),
+            b'\t' => Some(KeyCode::Tab),
+            0x7f | 0x08 => Some(KeyCode::Backspace),
+            b'[' => Some(KeyCode::BracketLeft),
+            b']' => Some(KeyCode::BracketRight),
+            _ => None,
+        };
+
+        if let Some(code) = code {
+            keys.push(TerminalKey::Key(code));
+        }
+    }
+
+    keys
+}
+
+fn parse_escape_sequence(seq: &[u8]) -> Option<KeyCode> {
+    match seq {
+        b"A" => Some(KeyCode::ArrowUp),
+        b"B" => Some(KeyCode::ArrowDown),
+        b"C" => Some(KeyCode::ArrowRight),
+        b"D" => Some(KeyCode::ArrowLeft),
+        b"P" => Some(KeyCode::F1),
+        b"Q" => Some(KeyCode::F2),
+        b"R" => Some(KeyCode::F3),
+        b"S" => Some(KeyCode::F4),
+        b"15~" => Some(KeyCode::F5),
+        b"17~" => Some(KeyCode::F6),
+        b"18~" => Some(KeyCode::F7),
+        b"19~" => Some(KeyCode::F8),
+        b"20~" => Some(KeyCode::F9),
+        b"21~" => Some(KeyCode::F10),
+        b"23~" => Some(KeyCode::F11),
+        b"24~" => Some(KeyCode::F12),
+        _ => None,
+    }
+}
+
+pub fn setup_terminal(mut commands: Commands, mut exit_app: MessageWriter<AppExit>) {
+    match RawTerminal::enable() {
+        Ok(terminal) => {
+            commands.insert_resource(TerminalScreen {
+                terminal,
+                pressed: Vec::new(),
+                last_frame: String::new(),
+            });
+            info!("Terminal frontend started");
+        }
+        Err(err) => {
+            error!("Failed to set up the terminal {}", err);
+            exit_app.write(AppExit::error());
+        }
+    }
+}
+
+pub fn read_terminal(
+    mut screen: ResMut<TerminalScreen>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut exit_app: MessageWriter<AppExit>,
+) {
+    keys.clear();
+    for code in screen.pressed.drain(..) {
+        keys.release(code);
+    }
+
+    for key in parse_terminal_keys(&screen.terminal.read()) {
+        match key {
+            TerminalKey::Interrupt => {
+                exit_app.write(AppExit::Success);
+            }
+            TerminalKey::Key(code) => {
+                keys.press(code);
+                screen.pressed.push(code);
+            }
+        }
+    }
+}
+
+pub fn draw_terminal(
+    game_lib: Res<GameLib>,
+    game: Res<Game>,
+    app_state: Res<State<AppState>>,
+    pause_state: Res<State<PauseState>>,
+    mut screen: ResMut<TerminalScreen>,
+) {
+    let board = &game.board;
+    let mut cells: Vec<Vec<Option<usize>>> = board.cells()[..board.main_rows()].to_vec();
+    if let Some((index, piece_pos)) = game.piece() {
+        for pos in game_lib.box_pos(index) {
+            let (row, col) = (piece_pos.row + pos.row, piece_pos.col + pos.col);
+            if let Some(cell) = cells
+                .get_mut(row as usize)
+                .and_then(|r| r.get_mut(col as usize))
+            {
+                *cell = Some(index.type_index);
+            }
+        }
+    }
+
+    let status = match (app_state.get(), pause_state.get()) {
+        (AppState::Stopped, _) => "GAME OVER",
+        (_, PauseState::Paused) => "PAUSED",
+        _ => "",
+    };
+    let mut side: Vec<String> = vec!["NEXT".to_string()];
+    side.extend(draw_piece(Some(game.next()), &game_lib));
+    side.push(String::new());
+    side.push("HOLD".to_string());
+    side.extend(draw_piece(game.hold(), &game_lib));
+    side.push(String::new());
+    side.push(format!("Score {}", game.scoreboard.score));
+    side.push(format!("Lines {}", game.scoreboard.lines));
+    side.push(format!("Level {}", game.scoreboard.level));
+    side.push(String::new());
+    side.push(status.to_string());
+    side.push("Ctrl-C to quit".to_string());
+
+    let width = board.col_count() * 2;
+    let mut lines: Vec<String> = vec![format!("+{}+", "-".repeat(width))];
+    for row in cells.iter().rev() {
+        let cells: String = row.iter().map(|c| draw_cell(*c, &game_lib)).collect();
+        lines.push(format!("|{}|", cells));
+    }
+    lines.push(format!("+{}+", "-".repeat(width)));
+
+    let mut frame = String::from("\x1b[H");
+    for (i, line) in lines.iter().enumerate() {
+        let side = side.get(i).map(|s| s.as_str()).unwrap_or("");
+        frame.push_str(&format!("{}   {}\x1b[K\r\n", line, side));
+    }
+
+    if frame != screen.last_frame {
+        let mut stdout = io::stdout();
+        if write!(stdout, "{}", frame)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return;
+        }
+        screen.last_frame = frame;
+    }
+}
+
+fn draw_cell(cell: Option<usize>, game_lib: &GameLib) -> String {
+    match cell.and_then(|t| game_lib.config.box_config.play_boxes.get(t)) {
+        Some(config) => {
+            let [r, g, b, _] = config.color().to_srgba().to_u8_array();
+            format!("\x1b[38;2;{};{};{}m██\x1b[0m", r, g, b)
+        }
+        None => "\x1b[2m .\x1b[0m".to_string(),
+    }
+}
+
+/// Draws a piece on its own, top row first.
+fn draw_piece(index: Option<&BoxIndex>, game_lib: &GameLib) -> Vec<String> {
+    let Some(index) = index else {
+        return vec![String::new()];
+    };
+
+    let positions = game_lib.box_pos(index);
+    let rows = positions.iter().map(|p| p.row);
+    let cols = positions.iter().map(|p| p.col);
+    let (min_row, max_row) = (rows.clone().min().unwrap_or(0), rows.max().unwrap_or(0));
+    let (min_col, max_col) = (cols.clone().min().unwrap_or(0), cols.max().unwrap_or(0));
+
+    (min_row..=max_row)
+        .rev()
+        .map(|row| {
+            (min_col..=max_col)
+                .map(|col| {
+                    if positions.contains(&BoxPos::new(row, col)) {
+                        draw_cell(Some(index.type_index), game_lib)
+                    } else {
+                        "  ".to_string()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(codes: &[KeyCode]) -> Vec<TerminalKey> {
+        codes.iter().map(|code| TerminalKey::Key(*code)).collect()
+    }
+
+    #[test]
+    fn arrow_keys_are_read_in_both_cursor_modes() {
+        assert_eq!(
+            parse_terminal_keys(b"\x1b[A\x1b[B\x1bOC\x1bOD"),
+            keys(&[
+                KeyCode::ArrowUp,
+                KeyCode::ArrowDown,
+                KeyCode::ArrowRight,
+                KeyCode::ArrowLeft,
+            ])
+        );
+    }
+
+    #[test]
+    fn function_keys_are_read_from_both_sequence_styles() {
+        assert_eq!(
+            parse_terminal_keys(b"\x1bOP\x1b[15~\x1b[20~\x1b[24~"),
+            keys(&[KeyCode::F1, KeyCode::F5, KeyCode::F9, KeyCode::F12])
+        );
+    }
+
+    #[test]
+    fn a_lone_escape_is_the_escape_key() {
+        assert_eq!(parse_terminal_keys(b"\x1b"), keys(&[KeyCode::Escape]));
+        assert_eq!(
+            parse_terminal_keys(b"\x1bq"),
+            keys(&[KeyCode::Escape, KeyCode::KeyQ])
+        );
+    }
+
+    #[test]
+    fn truncated_and_unknown_sequences_are_skipped() {
+        assert_eq!(parse_terminal_keys(b"a\x1b["), keys(&[KeyCode::KeyA]));
+        assert_eq!(parse_terminal_keys(b"\x1b[1"), Vec::new());
+        assert_eq!(parse_terminal_keys(b"\x1b[99~x"), keys(&[KeyCode::KeyX]));
+    }
+
+    #[test]
+    fn ctrl_c_interrupts_between_keys() {
+        assert_eq!(
+            parse_terminal_keys(b"a\x03 "),
+            vec![
+                TerminalKey::Key(KeyCode::KeyA),
+                TerminalKey::Interrupt,
+                TerminalKey::Key(KeyCode::Space),
+            ]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::bench::*;
+use crate::bot::*;
+use crate::game_lib::*;
+use crate::my_error::MyError;
+use crate::replay::*;
+use crate::utils::*;
+use bevy::prelude::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Best candidates copied unchanged into the next generation.
+const ELITE_COUNT: usize = 2;
+const TOURNAMENT_SIZE: usize = 3;
+/// Chance that a child gets one of its weights nudged.
+const MUTATION_RATE: f64 = 0.3;
+const MUTATION_STEP: f32 = 0.2;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Candidate {
+    pub weights: BotWeights,
+    /// Average lines cleared over the tuning seeds.
+    pub fitness: f64,
+}
+
+/// Tuning progress, written after every generation so an interrupted run can resume.
+/// The fitness of the best candidate only compares with runs on the same config and
+/// games, so those are stored too.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TuneCheckpoint {
+    pub config_hash: String,
+    pub games: u64,
+    pub first_seed: u64,
+    pub max_pieces: u64,
+    /// Generations evaluated so far.
+    pub generation: u32,
+    /// Candidates of the next generation.
+    pub population: Vec<BotWeights>,
+    pub best: Option<Candidate>,
+}
+
+impl TuneCheckpoint {
+    /// Starts from the configured weights plus random ones.
+    fn new(config_hash: String, tune: &TuneArgs, base: &BotWeights, rng: &mut StdRng) -> Self {
+        let mut population = vec![normalize(to_array(base))];
+        while population.len() < tune.population.max(1) {
+            population.push(normalize(std::array::from_fn(|_| {
+                rng.random_range(-1.0..1.0)
+            })));
+        }
+
+        Self {
+            config_hash,
+            games: tune.games,
+            first_seed: tune.first_seed,
+            max_pieces: tune.max_pieces,
+            generation: 0,
+            population,
+            best: None,
+        }
+    }
+
+    /// Fails unless the checkpoint was written by a run with the same config and games.
+    fn check(&self, config_hash: &str, tune: &TuneArgs) -> Result<(), MyError> {
+        let mismatch = |what: String| Err(MyError::MismatchedCheckpoint(what));
+        if self.config_hash != config_hash {
+            return mismatch("it was written for a different config".to_string());
+        }
+
+        for (name, saved, given) in [
+            ("games", self.games, tune.games),
+            ("first seed", self.first_seed, tune.first_seed),
+            ("max pieces", self.max_pieces, tune.max_pieces),
+        ] {
+            if saved != given {
+                return mismatch(format!("{} was {}, this run uses {}", name, saved, given));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn to_array(weights: &BotWeights) -> [f32; 4] {
+    [
+        weights.aggregate_height,
+        weights.holes,
+        weights.bumpiness,
+        weights.lines,
+    ]
+}
+
+/// Scales the weights to unit length. Only the ranking of placements matters to the bot,
+/// so weights pointing the same way play the same.
+fn normalize(values: [f32; 4]) -> BotWeights {
+    let length = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let [aggregate_height, holes, bumpiness, lines] = if length > 0.0 {
+        values.map(|v| v / length)
+    } else {
+        values
+    };
+
+    BotWeights {
+        aggregate_height,
+        holes,
+        bumpiness,
+        lines,
+    }
+}
+
+fn tournament<'a>(ranked: &'a [Candidate], rng: &mut StdRng) -> &'a Candidate {
+    (0..TOURNAMENT_SIZE)
+        .map(|_| &ranked[rng.random_range(0..ranked.len())])
+        .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+        .unwrap()
+}
+
+/// Builds the next generation from candidates ranked best first: the elite as they are,
+/// then children of tournament winners, averaged in favor of the fitter parent and
+/// sometimes mutated.
+fn breed(ranked: &[Candidate], size: usize, rng: &mut StdRng) -> Vec<BotWeights> {
+    let mut next: Vec<BotWeights> = ranked.iter().take(ELITE_COUNT).map(|c| c.weights).collect();
+
+    while next.len() < size {
+        let (a, b) = (tournament(ranked, rng), tournament(ranked, rng));
+        let total = a.fitness + b.fitness;
+        let share = if total > 0.0 { a.fitness / total } else { 0.5 } as f32;
+
+        let (a, b) = (to_array(&a.weights), to_array(&b.weights));
+        let mut child: [f32; 4] = std::array::from_fn(|i| a[i] * share + b[i] * (1.0 - share));
+        if rng.random_bool(MUTATION_RATE) {
+            child[rng.random_range(0..child.len())] +=
+                rng.random_range(-MUTATION_STEP..MUTATION_STEP);
+        }
+
+        next.push(normalize(child));
+    }
+
+    next
+}
+
+pub fn run_tune(args: &Args, tune: &TuneArgs) -> Result<(), MyError> {
+    let game_lib = GameLib::new(
+        &args.config_path,
+        &mut Assets::default(),
+        &mut Assets::default(),
+        true,
+    )?;
+    let hash = config_hash(&args.config_path)?;
+    let threads = tune.threads.unwrap_or_else(default_threads);
+    let seeds: Vec<u64> = (0..tune.games).map(|i| tune.first_seed + i).collect();
+
+    let mut checkpoint = if tune.checkpoint.exists() {
+        let checkpoint: TuneCheckpoint = read_json(&tune.checkpoint)?;
+        checkpoint.check(&hash, tune)?;
+        info!(
+            "Resuming tuning at generation {}",
+            checkpoint.generation + 1
+        );
+        checkpoint
+    } else {
+        let mut rng = StdRng::seed_from_u64(tune.tune_seed);
+        let base = bot_weights(args, &game_lib)?;
+        TuneCheckpoint::new(hash, tune, &base, &mut rng)
+    };
+
+    while checkpoint.generation < tune.generations {
+        let population = &checkpoint.population;
+        let jobs: Vec<(usize, u64)> = (0..population.len())
+            .flat_map(|c| seeds.iter().map(move |seed| (c, *seed)))
+            .collect();
+        let lines = parallel_map(&jobs, threads, |(c, seed)| {
+            play_game(&game_lib, *seed, &population[*c], tune.max_pieces).lines
+        });
+
+        let mut ranked: Vec<Candidate> = population
+            .iter()
+            .zip(lines.chunks(seeds.len().max(1)))
+            .map(|(weights, lines)| Candidate {
+                weights: *weights,
+                fitness: lines.iter().sum::<u32>() as f64 / lines.len().max(1) as f64,
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+
+        let mean = ranked.iter().map(|c| c.fitness).sum::<f64>() / ranked.len() as f64;
+        if checkpoint
+            .best
+            .as_ref()
+            .is_none_or(|best| ranked[0].fitness > best.fitness)
+        {
+            checkpoint.best = Some(ranked[0].clone());
+            write_json(&ranked[0].weights, &tune.output)?;
+        }
+
+        checkpoint.generation += 1;
+        let best = checkpoint.best.as_ref().unwrap();
+        info!(
+            "Generation {}: best={:.1} mean={:.1} overall={:.1} {:?}",
+            checkpoint.generation, ranked[0].fitness, mean, best.fitness, best.weights
+        );
+        println!(
+            "generation {:>3}  best {:>8.1}  mean {:>8.1}  overall best {:>8.1}",
+            checkpoint.generation, ranked[0].fitness, mean, best.fitness
+        );
+
+        let mut rng =
+            StdRng::seed_from_u64(tune.tune_seed.wrapping_add(checkpoint.generation as u64));
+        checkpoint.population = breed(&ranked, tune.population.max(1), &mut rng);
+
+        // Written aside first, so an interruption cannot leave a truncated checkpoint.
+        let partial = tune.checkpoint.with_extension("partial");
+        write_json(&checkpoint, &partial)?;
+        fs::rename(&partial, &tune.checkpoint)?;
+    }
+
+    if let Some(best) = &checkpoint.best {
+        println!(
+            "Best weights ({:.1} lines per game) written to {}",
+            best.fitness,
+            tune.output.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn tune_args(extra: &[&str]) -> Result<TuneArgs, clap::Error> {
+        let base = ["game", "--log-path", "log", "--config-path", "config.json"];
+        let tune = ["tune", "--output", "out.json", "--checkpoint", "tune.json"];
+        let args = Args::try_parse_from(base.iter().chain(tune.iter()).chain(extra.iter()))?;
+        match args.command {
+            Some(ToolCommand::Tune(tune)) => Ok(tune),
+            _ => panic!("not a tune command"),
+        }
+    }
+
+    #[test]
+    fn tuning_needs_at_least_one_game() {
+        assert!(tune_args(&["--games", "0"]).is_err());
+        assert_eq!(tune_args(&["--games", "1"]).unwrap().games, 1);
+    }
+
+    #[test]
+    fn checkpoints_only_resume_the_same_run() {
+        let tune = tune_args(&["--games", "4", "--first-seed", "10"]).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let base = BotWeights {
+            aggregate_height: -0.5,
+            holes: -0.4,
+            bumpiness: -0.2,
+            lines: 0.8,
+        };
+        let checkpoint = TuneCheckpoint::new("hash".to_string(), &tune, &base, &mut rng);
+        assert!(checkpoint.check("hash", &tune).is_ok());
+        assert!(checkpoint.check("other", &tune).is_err());
+
+        for args in [
+            ["--games", "5", "--first-seed", "10", "--max-pieces", "500"],
+            ["--games", "4", "--first-seed", "11", "--max-pieces", "500"],
+            ["--games", "4", "--first-seed", "10", "--max-pieces", "100"],
+        ] {
+            let changed = tune_args(&args).unwrap();
+            assert!(checkpoint.check("hash", &changed).is_err());
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use crate::my_error::MyError;
 use bevy::prelude::*;
-use clap::Parser;
-use serde::{Deserialize, de::DeserializeOwned};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json;
 use std::{
-    fs::File,
-    io::BufReader,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
     path::{Path, PathBuf},
 };
 use tracing_appender::non_blocking::WorkerGuard;
@@ -18,60 +18,131 @@ pub struct Args {
 
     #[arg(short, long)]
     pub config_path: PathBuf,
-}
 
-#[derive(Resource)]
-pub struct DropDownTimer(pub Timer);
+    /// Overrides `bindings_path` from the config file.
+    #[arg(short, long, alias = "key-bindings-path")]
+    pub bindings_path: Option<PathBuf>,
+
+    /// Continues the game saved with the save action instead of starting a new one.
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Seeds the piece generator, overriding `seed` from the config file and the
+    /// input script.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Plays back a recorded replay file instead of reading player input.
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Runs an input script, in JSON or the line format, instead of reading player
+    /// input and checks the board assertions it contains. Needs a seed from the script,
+    /// `--seed` or the config file.
+    #[arg(long, conflicts_with = "replay")]
+    pub script: Option<PathBuf>,
+
+    /// Runs the game without a window or renderer as fast as possible, then prints a
+    /// result summary and exits.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Plays in the terminal instead of a window, drawing the board with colored
+    /// characters. Only available on Unix.
+    #[arg(long, conflicts_with = "headless")]
+    pub tui: bool,
+
+    /// Lets the built-in bot play, in the window or together with `--headless`.
+    #[arg(long, conflicts_with_all = ["replay", "script"])]
+    pub bot: bool,
 
-#[derive(Resource)]
-pub struct CountDownTimer {
-    timer: Timer,
-    max_count: u32,
-    pub count: u32,
+    /// Serves the game as a step-based environment speaking newline-delimited JSON over
+    /// stdin and stdout, for training agents.
+    #[arg(long, conflicts_with_all = ["replay", "script", "headless", "tui", "bot", "resume"])]
+    pub env: bool,
+
+    /// Two players on split boards in one window, sending garbage to each other.
+    #[arg(long, conflicts_with_all = ["replay", "script", "headless", "tui", "bot", "resume", "env"])]
+    pub versus: bool,
+
+    /// Bot weights file written by the `tune` command, replacing `bot_config.weights`.
+    #[arg(long)]
+    pub weights: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<ToolCommand>,
 }
 
-impl CountDownTimer {
-    pub fn new(duration: f32, max_count: u32) -> Self {
-        Self {
-            timer: repeat_timer(duration),
-            max_count,
-            count: 0,
-        }
-    }
+/// Tools run instead of the game.
+#[derive(Subcommand)]
+pub enum ToolCommand {
+    /// Plays seeded games with the bot on all cores and reports how they went.
+    Bench(BenchArgs),
+    /// Evolves the bot's weights with a genetic algorithm over seeded bot games.
+    Tune(TuneArgs),
+}
 
-    pub fn start(&mut self) {
-        self.timer.unpause();
-        self.count = 0;
-    }
+#[derive(clap::Args)]
+pub struct BenchArgs {
+    /// Number of games to play.
+    #[arg(short, long, default_value_t = 100)]
+    pub games: u64,
 
-    pub fn is_finished(&self) -> bool {
-        self.count >= self.max_count
-    }
+    /// Seed of the first game; the others use the following seeds.
+    #[arg(long, default_value_t = 0)]
+    pub first_seed: u64,
 
-    pub fn update(&mut self, time: &Time) -> bool {
-        if self.is_finished() {
-            return false;
-        }
+    /// Worker threads; defaults to the number of cores.
+    #[arg(short, long)]
+    pub threads: Option<usize>,
 
-        self.timer.tick(time.delta());
-        if self.timer.is_finished() {
-            self.count += 1;
-            true
-        } else {
-            false
-        }
-    }
+    /// Ends a game after this many pieces, since a good bot may never top out.
+    #[arg(long, default_value_t = 1000)]
+    pub max_pieces: u64,
 
-    pub fn stop(&mut self) {
-        self.timer.pause();
-    }
+    /// Prints the report as JSON instead of a table.
+    #[arg(long)]
+    pub json: bool,
 }
 
-#[derive(Resource)]
-pub struct FastDownTimer(pub CountDownTimer);
+#[derive(clap::Args)]
+pub struct TuneArgs {
+    /// Where the best weights found so far are written.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Progress file written after every generation; tuning resumes from it when it
+    /// exists, as long as it was written for the same config and games.
+    #[arg(long)]
+    pub checkpoint: PathBuf,
 
-#[derive(Resource)]
-pub struct FlashFullLineTimer(pub CountDownTimer);
+    #[arg(long, default_value_t = 30)]
+    pub generations: u32,
+
+    /// Candidate weights per generation.
+    #[arg(long, default_value_t = 24)]
+    pub population: usize,
+
+    /// Games each candidate plays, always on the same seeds.
+    #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..))]
+    pub games: u64,
+
+    /// Seed of the first game; the others use the following seeds.
+    #[arg(long, default_value_t = 0)]
+    pub first_seed: u64,
+
+    /// Ends a game after this many pieces, since a good bot may never top out.
+    #[arg(long, default_value_t = 500)]
+    pub max_pieces: u64,
+
+    /// Seed of the random mutations.
+    #[arg(long, default_value_t = 0)]
+    pub tune_seed: u64,
+
+    /// Worker threads; defaults to the number of cores.
+    #[arg(short, long)]
+    pub threads: Option<usize>,
+}
 
 #[derive(Debug, Deserialize, Resource)]
 pub struct ISize {
@@ -85,12 +156,6 @@ pub struct RectSize {
     pub height: f32,
 }
 
-pub fn repeat_timer(duration: f32) -> Timer {
-    let mut timer = Timer::from_seconds(duration, TimerMode::Repeating);
-    timer.pause();
-    timer
-}
-
 pub fn vec_to_vec2(v: &[f32; 2]) -> Vec2 {
     Vec2 { x: v[0], y: v[1] }
 }
@@ -110,6 +175,64 @@ where
     Ok(result)
 }
 
+/// Writes `value` as pretty-printed JSON, creating the parent directories if needed.
+pub fn write_json<T, P>(value: &T, path: P) -> Result<(), MyError>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    if let Some(dir) = path.as_ref().parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(BufWriter::new(file), value)?;
+    Ok(())
+}
+
+pub const APP_NAME: &str = "my_bevy_game";
+
+/// Per-user directory for files written by the game, following the platform
+/// conventions: `$XDG_DATA_HOME` or `~/.local/share` on Linux, `%APPDATA%` on
+/// Windows and `~/Library/Application Support` on macOS.
+pub fn user_data_dir() -> PathBuf {
+    let var = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+
+    let base = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+
+    base.unwrap_or_else(|| PathBuf::from(".")).join(APP_NAME)
+}
+
+/// Formats seconds since the Unix epoch as a `YYYY-MM-DD` UTC date.
+pub fn format_date(secs: u64) -> String {
+    // Civil-from-days conversion, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 pub fn setup_log<P: AsRef<Path>>(log_path: P) -> WorkerGuard {
     let log_file = File::create(log_path.as_ref()).expect("Open file");
     let (non_blocking_appender, guard) = tracing_appender::non_blocking(log_file);
@@ -155,6 +278,12 @@ pub fn get_box_pos(origin: &Vec2, row: i32, col: i32, span: f32) -> Vec2 {
     *origin + offset
 }
 
+/// Inverse of [`get_box_pos`]: returns the `(row, col)` of the cell containing `pos`.
+pub fn get_box_row_col(origin: &Vec2, pos: &Vec2, span: f32) -> (i32, i32) {
+    let cell = ((*pos - *origin) / span).round();
+    (cell.y as i32, cell.x as i32)
+}
+
 pub fn create_rect(
     pos: &Vec2,
     z: f32,
@@ -163,12 +292,37 @@ pub fn create_rect(
     commands: &mut Commands,
     meshes: &mut Assets<Mesh>,
     materials: &mut Assets<ColorMaterial>,
-) {
+) -> Entity {
     let mesh = meshes.add(Rectangle::new(size.width, size.height));
     let material = materials.add(color);
-    commands.spawn((
-        Mesh2d(mesh),
-        MeshMaterial2d(material),
-        Transform::from_xyz(pos.x, pos.y, z),
-    ));
+    commands
+        .spawn((
+            Mesh2d(mesh),
+            MeshMaterial2d(material),
+            Transform::from_xyz(pos.x, pos.y, z),
+        ))
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_date_at_epoch() {
+        assert_eq!(format_date(0), "1970-01-01");
+    }
+
+    #[test]
+    fn format_date_on_leap_days() {
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_164_800 + 86_399), "2024-02-29");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+    }
+
+    #[test]
+    fn format_date_across_year_boundary() {
+        assert_eq!(format_date(1_704_067_199), "2023-12-31");
+        assert_eq!(format_date(1_704_067_200), "2024-01-01");
+    }
 }
@@ -0,0 +1,746 @@
+use crate::accessibility::*;
+use crate::game_lib::*;
+use crate::game_panel::*;
+use crate::input::*;
+use crate::layout::*;
+use crate::line_clear::*;
+use crate::messages::*;
+use crate::motion::*;
+use crate::play_box::*;
+use crate::preview::*;
+use crate::replay::*;
+use crate::rules::*;
+use crate::systems::*;
+use crate::theme::*;
+use crate::utils::*;
+use bevy::{input::InputSystems, prelude::*, window::WindowResized};
+use rand::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+pub const PLAYER_COUNT: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct VersusConfig {
+    /// Design size of the window holding both boards.
+    pub window_size: ISize,
+    /// Design space offset of the second player's panels from the configured positions.
+    pub player_offset: [f32; 2],
+    pub bindings_paths: [PathBuf; PLAYER_COUNT],
+    /// Garbage rows sent for clearing 1, 2, 3, ... lines at once; the last entry is used
+    /// for any larger count.
+    pub garbage_lines: Vec<u32>,
+    pub garbage_color: [u8; 4],
+}
+
+impl VersusConfig {
+    pub fn garbage_for(&self, lines: usize) -> u32 {
+        let index = lines
+            .saturating_sub(1)
+            .min(self.garbage_lines.len().saturating_sub(1));
+        self.garbage_lines.get(index).copied().unwrap_or(0)
+    }
+}
+
+/// Local two-player mode, enabled with `--versus`. Each player entity plays its own
+/// [`GameRules`], so the single-player resources and systems are not used.
+pub struct VersusPlugin;
+
+impl Plugin for VersusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SelectTheme>()
+            .add_systems(Startup, setup_versus)
+            .add_systems(
+                PreUpdate,
+                read_versus_input
+                    .run_if(resource_exists::<VersusMatch>)
+                    .after(InputSystems),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    play_versus
+                        .run_if(resource_exists::<VersusMatch>.and(in_state(PauseState::Running))),
+                    animate_boxes,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_versus_pause,
+                    start_rematch,
+                    update_versus_status,
+                    update_versus_layout,
+                )
+                    .chain()
+                    .run_if(resource_exists::<VersusMatch>),
+            )
+            .add_systems(
+                Update,
+                toggle_smooth_motion.run_if(resource_exists::<MotionConfig>),
+            )
+            .add_systems(
+                Update,
+                (
+                    switch_theme.run_if(resource_exists::<ThemeManager>),
+                    switch_accessibility.run_if(resource_exists::<AccessibilitySettings>),
+                    apply_theme.run_if(
+                        resource_exists_and_changed::<ThemeManager>
+                            .or(resource_exists_and_changed::<AccessibilitySettings>),
+                    ),
+                    update_piece_glyphs.run_if(resource_exists::<AccessibilitySettings>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// State of the match between the players.
+#[derive(Resource, Debug, Default)]
+pub struct VersusMatch {
+    pub over: bool,
+    /// The player still standing; `None` on a draw or while playing.
+    pub winner: Option<usize>,
+}
+
+/// One side of a versus match, with its own rules, pieces and input.
+#[derive(Component)]
+pub struct VersusPlayer {
+    pub id: usize,
+    pub rules: GameRules,
+    pub game_panel: GamePanel,
+    pub play_box: PlayBox,
+    pub preview: Preview,
+    pub hold: Preview,
+    line_clearer: LineClearer,
+    pub bindings: InputBindings,
+    pub actions: ActionState,
+    /// Actions triggered since the last tick.
+    pub pending: ActionFrame,
+    /// Picks the hole column of incoming garbage.
+    rng: StdRng,
+}
+
+impl VersusPlayer {
+    pub fn new(
+        id: usize,
+        seed: u64,
+        bindings: InputBindings,
+        commands: &mut Commands,
+        game_lib: &GameLib,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Self {
+        let config = &game_lib.config;
+        let offset = vec_to_vec2(&config.versus_config.player_offset) * id as f32;
+        let mut player = Self {
+            id,
+            rules: new_rules(seed, game_lib),
+            game_panel: GamePanel::new_at(offset, commands, game_lib, meshes, materials),
+            play_box: PlayBox::default(),
+            preview: Preview::new_at(
+                PreviewKind::Next,
+                offset,
+                commands,
+                game_lib,
+                meshes,
+                materials,
+            ),
+            hold: Preview::new_at(
+                PreviewKind::Hold,
+                offset,
+                commands,
+                game_lib,
+                meshes,
+                materials,
+            ),
+            line_clearer: LineClearer::new(&config.line_clear_config),
+            bindings,
+            actions: ActionState::default(),
+            pending: ActionFrame::default(),
+            rng: StdRng::seed_from_u64(seed.wrapping_add(id as u64)),
+        };
+        PieceView {
+            game_panel: &mut player.game_panel,
+            play_box: &mut player.play_box,
+            preview: &mut player.preview,
+            hold: &mut player.hold,
+        }
+        .reset(&player.rules, commands, game_lib);
+
+        info!("Versus player {} initialized", id + 1);
+
+        player
+    }
+
+    /// Clears the board and starts over with a new piece sequence.
+    fn reset(&mut self, seed: u64, ctx: &mut EffectContext) {
+        let (commands, game_lib) = (&mut *ctx.commands, ctx.game_lib);
+        self.rules.board.clear();
+        self.game_panel
+            .sync(&mut self.rules.board, commands, game_lib);
+        self.rules = new_rules(seed, game_lib);
+        PieceView {
+            game_panel: &mut self.game_panel,
+            play_box: &mut self.play_box,
+            preview: &mut self.preview,
+            hold: &mut self.hold,
+        }
+        .reset(&self.rules, commands, game_lib);
+        // A clear still playing when the match ended is frozen on screen.
+        self.line_clearer.finish(ctx);
+        self.pending = ActionFrame::default();
+        self.rng = StdRng::seed_from_u64(seed.wrapping_add(self.id as u64));
+    }
+
+    /// Runs one tick of the rules and returns the garbage rows sent to the opponent.
+    /// Clearing lines cancels pending garbage first, and whatever is left is sent.
+    fn tick(
+        &mut self,
+        time: &Time,
+        box_materials: &Query<&MeshMaterial2d<ColorMaterial>>,
+        ctx: &mut EffectContext,
+        gameplay_messages: &mut MessageWriter<GameplayMessage>,
+    ) -> u32 {
+        if self.rules.is_over() {
+            return 0;
+        }
+
+        let game_lib = ctx.game_lib;
+        let was_clearing = self.rules.phase() == Phase::Clearing;
+        let frame = std::mem::take(&mut self.pending);
+        let events = self.rules.tick(&frame, game_lib);
+        PieceView {
+            game_panel: &mut self.game_panel,
+            play_box: &mut self.play_box,
+            preview: &mut self.preview,
+            hold: &mut self.hold,
+        }
+        .update(&events, &self.rules, ctx.commands, game_lib);
+        gameplay_messages.write_batch(events.iter().filter_map(gameplay_message));
+
+        let mut sent = 0;
+        let mut cleared = false;
+        for event in events.iter() {
+            if let RuleEvent::LinesCleared(count) = event {
+                let attack = game_lib.config.versus_config.garbage_for(*count);
+                sent += self.rules.cancel_garbage(attack);
+                cleared = true;
+            }
+        }
+
+        if was_clearing {
+            self.line_clearer.update(time, ctx);
+            if self.rules.phase() != Phase::Clearing {
+                self.line_clearer.finish(ctx);
+            }
+        }
+
+        if self.rules.board.has_changes() {
+            self.game_panel
+                .sync(&mut self.rules.board, ctx.commands, game_lib);
+        }
+
+        if cleared {
+            let boxes = cleared_boxes(
+                &self.game_panel,
+                &self.rules.board,
+                box_materials,
+                ctx.commands,
+                game_lib,
+            );
+            self.line_clearer.start(boxes, ctx);
+        }
+
+        if self.rules.is_over() {
+            gameplay_messages.write(GameplayMessage::GameOver);
+            info!("Versus player {} topped out", self.id + 1);
+        }
+
+        sent
+    }
+}
+
+/// Rules of one player; both players of a match draw the same piece sequence.
+fn new_rules(seed: u64, game_lib: &GameLib) -> GameRules {
+    let config = &game_lib.config;
+    let index_gen = IndexGen::with_seed(
+        config.box_config.play_box_type_count(),
+        PLAY_BOX_ROTATE_COUNT,
+        seed,
+    );
+    GameRules::new(game_lib, index_gen, config.tick_rate)
+}
+
+/// Text showing the score of one player.
+#[derive(Component, Debug)]
+pub struct VersusStatus(pub usize);
+
+fn match_seed(args: &Args, game_lib: &GameLib) -> u64 {
+    args.seed
+        .or(game_lib.config.seed)
+        .unwrap_or_else(rand::random)
+}
+
+pub fn setup_versus(
+    mut commands: Commands,
+    args: Res<Args>,
+    mut exit_app: MessageWriter<AppExit>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    window: Option<Single<&mut Window>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    let mut game_lib = match GameLib::new(
+        args.config_path.as_path(),
+        meshes.as_mut(),
+        materials.as_mut(),
+        false,
+    ) {
+        Ok(lib) => lib,
+        Err(err) => {
+            error!("Failed to initialize GameLib {}", err);
+            exit_app.write(AppExit::error());
+            return;
+        }
+    };
+
+    let theme_manager = match ThemeManager::new(&game_lib.config.theme_config) {
+        Ok(manager) => manager,
+        Err(err) => {
+            error!("Failed to initialize ThemeManager {}", err);
+            exit_app.write(AppExit::error());
+            return;
+        }
+    };
+
+    let mut bindings: Vec<InputBindings> = Vec::new();
+    for path in game_lib.config.versus_config.bindings_paths.iter() {
+        match InputBindings::load(path) {
+            Ok(b) => bindings.push(b),
+            Err(err) => {
+                error!("Failed to load input bindings {}: {}", path.display(), err);
+                exit_app.write(AppExit::error());
+                return;
+            }
+        }
+    }
+
+    let window_size = &game_lib.config.versus_config.window_size;
+    let design_size = Vec2::new(window_size.width as f32, window_size.height as f32);
+    game_lib.set_design_size(design_size);
+    if let Some(mut window) = window {
+        window.resolution.set(design_size.x, design_size.y);
+    }
+    commands.spawn(Camera2d);
+
+    let config = &game_lib.config;
+    fixed_time.set_timestep_hz(config.tick_rate);
+    let seed = match_seed(&args, &game_lib);
+    info!("Starting versus match with seed {}", seed);
+
+    for (id, bindings) in bindings.into_iter().enumerate() {
+        let player = VersusPlayer::new(
+            id,
+            seed,
+            bindings,
+            &mut commands,
+            &game_lib,
+            meshes.as_mut(),
+            materials.as_mut(),
+        );
+        commands.spawn(player);
+    }
+
+    for id in 0..PLAYER_COUNT {
+        let mut node = Node {
+            position_type: PositionType::Absolute,
+            top: px(8),
+            ..default()
+        };
+        if id == 0 {
+            node.left = px(8);
+        } else {
+            node.right = px(8);
+        }
+        commands.spawn((
+            node,
+            Text::new(""),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            VersusStatus(id),
+        ));
+    }
+
+    commands.insert_resource(config.motion_config.clone());
+    commands.insert_resource(config.audio_config.clone());
+    commands.insert_resource(AccessibilitySettings::new(&config.accessibility_config));
+    commands.insert_resource(theme_manager);
+    commands.insert_resource(VersusMatch::default());
+    commands.insert_resource(game_lib);
+
+    info!("Finished setting up versus match");
+}
+
+pub fn read_versus_input(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut players: Query<&mut VersusPlayer>,
+) {
+    for mut player in players.iter_mut() {
+        // Gamepads are handed out to the players in connection order.
+        let gamepad = gamepads.iter().nth(player.id);
+        let pressed = player.bindings.pressed_actions(keys.as_ref(), gamepad);
+        let repeat = player.bindings.repeat.clone();
+        player.actions.update(pressed, time.delta_secs(), &repeat);
+
+        let frame = player.actions.frame();
+        player.pending.merge(&frame);
+    }
+}
+
+pub fn play_versus(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_lib: Res<GameLib>,
+    mut versus: ResMut<VersusMatch>,
+    mut players: Query<&mut VersusPlayer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    box_materials: Query<&MeshMaterial2d<ColorMaterial>>,
+    mut gameplay_messages: MessageWriter<GameplayMessage>,
+) {
+    if versus.over {
+        return;
+    }
+
+    let mut ctx = EffectContext {
+        commands: &mut commands,
+        materials: materials.as_mut(),
+        game_lib: game_lib.as_ref(),
+    };
+    let mut sent = [0; PLAYER_COUNT];
+    for mut player in players.iter_mut() {
+        sent[player.id] = player.tick(
+            time.as_ref(),
+            &box_materials,
+            &mut ctx,
+            &mut gameplay_messages,
+        );
+    }
+
+    let mut standing: Vec<usize> = Vec::new();
+    for mut player in players.iter_mut() {
+        let received: u32 = (0..PLAYER_COUNT)
+            .filter(|id| *id != player.id)
+            .map(|id| sent[id])
+            .sum();
+        if received > 0 {
+            let col_count = player.rules.board.col_count();
+            let hole_col = player.rng.random_range(0..col_count);
+            player.rules.receive_garbage(received, hole_col);
+        }
+
+        if !player.rules.is_over() {
+            standing.push(player.id);
+        }
+    }
+
+    if standing.len() < PLAYER_COUNT {
+        versus.over = true;
+        versus.winner = (standing.len() == 1).then(|| standing[0]);
+        match versus.winner {
+            Some(id) => info!("Versus match won by player {}", id + 1),
+            None => info!("Versus match ended in a draw"),
+        }
+    }
+}
+
+pub fn toggle_versus_pause(
+    versus: Res<VersusMatch>,
+    players: Query<&VersusPlayer>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if versus.over
+        || !players
+            .iter()
+            .any(|p| p.actions.just_pressed(InputAction::Pause))
+    {
+        return;
+    }
+
+    match pause_state.get() {
+        PauseState::Running => {
+            info!("Versus match paused");
+            next_pause_state.set(PauseState::Paused);
+        }
+        PauseState::Paused => {
+            info!("Versus match resumed");
+            next_pause_state.set(PauseState::Running);
+        }
+    }
+}
+
+/// Starts a new match with F9 once the current one is over.
+pub fn start_rematch(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    game_lib: Res<GameLib>,
+    mut versus: ResMut<VersusMatch>,
+    mut players: Query<&mut VersusPlayer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !versus.over || !keys.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    let seed = match_seed(&args, &game_lib);
+    info!("Starting versus rematch with seed {}", seed);
+
+    let mut ctx = EffectContext {
+        commands: &mut commands,
+        materials: materials.as_mut(),
+        game_lib: game_lib.as_ref(),
+    };
+    for mut player in players.iter_mut() {
+        player.reset(seed, &mut ctx);
+    }
+    *versus = VersusMatch::default();
+}
+
+pub fn update_versus_status(
+    versus: Res<VersusMatch>,
+    pause_state: Res<State<PauseState>>,
+    players: Query<&VersusPlayer>,
+    mut texts: Query<(&VersusStatus, &mut Text)>,
+) {
+    for (status, mut text) in texts.iter_mut() {
+        let Some(player) = players.iter().find(|p| p.id == status.0) else {
+            continue;
+        };
+
+        let result = if versus.over {
+            match versus.winner {
+                Some(id) if id == player.id => "WINS - F9 for a rematch",
+                Some(_) => "LOSES",
+                None => "DRAW - F9 for a rematch",
+            }
+        } else if *pause_state.get() == PauseState::Paused {
+            "PAUSED"
+        } else {
+            ""
+        };
+        let value = format!(
+            "Player {}  Score {}  Lines {}  Garbage {}  {}",
+            player.id + 1,
+            player.rules.scoreboard.score,
+            player.rules.scoreboard.lines,
+            player.rules.incoming_garbage(),
+            result
+        );
+
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+}
+
+pub fn update_versus_layout(
+    mut resize_events: MessageReader<WindowResized>,
+    mut commands: Commands,
+    mut game_lib: ResMut<GameLib>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut anchors: Query<(&LayoutAnchor, &mut Transform)>,
+    mut players: Query<&mut VersusPlayer>,
+) {
+    let Some(event) = resize_events.read().last() else {
+        return;
+    };
+
+    game_lib.update_layout(Vec2::new(event.width, event.height), meshes.as_mut());
+
+    for (anchor, mut transform) in anchors.iter_mut() {
+        anchor.apply(transform.as_mut(), game_lib.as_ref());
+    }
+
+    for mut player in players.iter_mut() {
+        let player = player.as_mut();
+        player
+            .game_panel
+            .update_layout(&mut commands, game_lib.as_ref());
+        player
+            .preview
+            .update_layout(&mut commands, game_lib.as_ref());
+        player.hold.update_layout(&mut commands, game_lib.as_ref());
+        player.play_box.update_pos_vis(
+            &player.game_panel.play_region,
+            &mut commands,
+            game_lib.as_ref(),
+            MoveKind::Snap,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::*;
+    use bevy::ecs::world::CommandQueue;
+
+    fn game_lib() -> GameLib {
+        GameLib::new(
+            "assets/game_config.json",
+            &mut Assets::default(),
+            &mut Assets::default(),
+            true,
+        )
+        .unwrap()
+    }
+
+    /// An app running `play_versus` for two players drawing from `seed`.
+    fn versus_app(seed: u64) -> App {
+        let mut app = App::new();
+        app.add_message::<GameplayMessage>()
+            .init_resource::<Time>()
+            .init_resource::<Assets<ColorMaterial>>()
+            .init_resource::<VersusMatch>()
+            .insert_resource(game_lib())
+            .add_systems(Update, play_versus);
+
+        let game_lib = game_lib();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, app.world());
+        let players: Vec<VersusPlayer> = game_lib
+            .config
+            .versus_config
+            .bindings_paths
+            .iter()
+            .enumerate()
+            .map(|(id, path)| {
+                VersusPlayer::new(
+                    id,
+                    seed,
+                    InputBindings::load(path).unwrap(),
+                    &mut commands,
+                    &game_lib,
+                    &mut Assets::default(),
+                    &mut Assets::default(),
+                )
+            })
+            .collect();
+        queue.apply(app.world_mut());
+        for player in players {
+            app.world_mut().spawn(player);
+        }
+        app
+    }
+
+    fn player(app: &mut App, id: usize) -> Mut<'_, VersusPlayer> {
+        let mut players = app.world_mut().query::<&mut VersusPlayer>();
+        players
+            .iter_mut(app.world_mut())
+            .find(|p| p.id == id)
+            .unwrap()
+    }
+
+    /// Gives player `id` a vertical piece above four rows that are full but for their
+    /// first column, so a hard drop clears them all.
+    fn set_up_four_lines(app: &mut App, id: usize) {
+        let game_lib = game_lib();
+        let (index, shape) = game_lib
+            .box_positions
+            .iter()
+            .enumerate()
+            .flat_map(|(type_index, rotations)| {
+                rotations
+                    .iter()
+                    .enumerate()
+                    .map(move |(rotate_index, shape)| (type_index, rotate_index, shape))
+            })
+            .find(|(_, _, shape)| shape.len() == 4 && shape.iter().all(|p| p.col == shape[0].col))
+            .map(|(type_index, rotate_index, shape)| {
+                let index = BoxIndex {
+                    type_index,
+                    rotate_index,
+                };
+                (index, shape.clone())
+            })
+            .unwrap();
+
+        let mut player = player(app, id);
+        let rules = &mut player.rules;
+        let col_count = rules.board.col_count();
+        let row = vec![None]
+            .into_iter()
+            .chain(std::iter::repeat_n(Some(0), col_count - 1))
+            .collect::<Vec<_>>();
+        rules.board.restore(&vec![row; 4]);
+
+        let min_row = shape.iter().map(|p| p.row).min().unwrap();
+        let pos = BoxPos::new(8 - min_row, -shape[0].col);
+        let index_gen = rules.index_gen().clone();
+        let next = rules.next().clone();
+        rules.resume(index_gen, Some((index, pos)), next, None, true, 0);
+        player.pending = ActionFrame {
+            actions: vec![InputAction::HardDrop],
+            target_col: None,
+        };
+    }
+
+    #[test]
+    fn garbage_grows_with_the_lines_cleared() {
+        let mut config = VersusConfig {
+            window_size: ISize {
+                width: 1280,
+                height: 720,
+            },
+            player_offset: [640.0, 0.0],
+            bindings_paths: Default::default(),
+            garbage_lines: vec![0, 1, 2, 4],
+            garbage_color: [128, 128, 128, 255],
+        };
+
+        let sent: Vec<u32> = (1..=6).map(|lines| config.garbage_for(lines)).collect();
+        assert_eq!(sent, [0, 1, 2, 4, 4, 4]);
+
+        config.garbage_lines.clear();
+        assert_eq!(config.garbage_for(4), 0);
+    }
+
+    #[test]
+    fn cleared_lines_cancel_incoming_garbage_and_send_the_rest() {
+        let mut app = versus_app(7);
+        // Both players spawn their first piece.
+        app.update();
+        set_up_four_lines(&mut app, 0);
+        player(&mut app, 0).rules.receive_garbage(1, 3);
+        app.update();
+
+        let attack = game_lib().config.versus_config.garbage_for(4);
+        let sender = player(&mut app, 0);
+        assert_eq!(sender.rules.phase(), Phase::Clearing);
+        assert_eq!(sender.rules.board.full_rows().len(), 4);
+        assert_eq!(sender.rules.incoming_garbage(), 0);
+        assert_eq!(player(&mut app, 1).rules.incoming_garbage(), attack - 1);
+
+        // The receiver's next lock raises the garbage instead of clearing lines.
+        player(&mut app, 1).pending = ActionFrame {
+            actions: vec![InputAction::HardDrop],
+            target_col: None,
+        };
+        app.update();
+
+        let receiver = player(&mut app, 1);
+        let garbage = Some(game_lib().config.box_config.garbage_type_index());
+        assert_eq!(receiver.rules.incoming_garbage(), 0);
+        for row in &receiver.rules.board.cells()[..(attack - 1) as usize] {
+            assert_eq!(row.iter().filter(|c| **c == garbage).count(), row.len() - 1);
+        }
+        assert!(!app.world().resource::<VersusMatch>().over);
+    }
+}